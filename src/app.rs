@@ -1,41 +1,66 @@
-use std::{cmp::min, fs, io};
+use std::{io, ops::Range};
 
 use ratatui::{
     DefaultTerminal, Frame,
-    crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     layout::{Alignment, Constraint, Direction, Layout, Position},
     prelude::Rect,
+    style::Modifier,
     style::{Color, Style},
     text::{Line, Span, Text},
-    widgets::{Block, Borders, Paragraph, Wrap},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
 };
+
+use regex::Regex;
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 use crate::{
-    command::{
-        AddLineCommand, InsertIntoLineCommand, LineCommandContext, MergeLineCommand,
-        RemoveFromLineCommand, SplitLineCommand, TextEditorLineCommand,
-    },
-    gap_buffer::LinesGapBuffer,
+    buffer::{Buffer, Viewport},
+    command_line::{CommandLine, ExCommand, parse},
+    grapheme_reader::{GraphemeReader, line_end, line_start},
+    registers::{Clipboard, Register, RegisterWrite, Registers},
+    search::RegexMatch,
+    status_line::{DEFAULT_SEGMENTS, StatusSegment, parse_segments, segment_names},
     text_representation::TextRepresentation,
+    utils::read_or_create_file,
+    vi::{
+        CommandParser, Motion, Operator, Parsed, ViCommand, line_range, motion_target,
+        operator_range,
+    },
+    window::{Split, Window},
+    words::{next_word_boundary, previous_word_boundary, word_at},
 };
+const LINE_NUMBERS_WIDTH: u16 = 5;
 pub struct App<T: TextRepresentation> {
     exit: bool,
     mode: Mode,
-    row_number: usize,
-    column_number: usize,
-    text_representation: T,
-    index: usize,
-    cursor_up_and_down_column_position_locked: bool,
-    global_up_and_down_column_position: usize,
-    undo_line_commands: Vec<Box<dyn TextEditorLineCommand>>,
-    redo_line_commands: Vec<Box<dyn TextEditorLineCommand>>,
+    buffers: Vec<Buffer<T>>,
+    windows: Vec<Window>,
+    current_window: usize,
+    layout: Split,
+    pending_window_command: bool,
+    search_query: String,
+    search_origin: Option<Viewport>,
+    command_line: CommandLine,
+    replace: ReplaceState,
+    vi_parser: CommandParser,
+    registers: Registers,
+    active_register: Option<char>,
+    pending_register_insert: bool,
+    clipboard: Option<Box<dyn Clipboard>>,
+    last_change: Option<(ViCommand, Option<String>)>,
+    insert_recording: Option<(ViCommand, String)>,
+    selection_anchor: usize,
+    pending_text_object: bool,
+    show_buffer_list: bool,
     window_height: usize,
     window_width: usize,
-    lines_text_editor: LinesGapBuffer,
     wrapping: bool,
-    page_index: usize,
-    page_start: usize,
+    wrap_at_words: bool,
+    hanging_indent: bool,
+    status_segments: Vec<StatusSegment>,
+    status_message: String,
 }
 #[derive(Default)]
 enum Mode {
@@ -43,337 +68,1195 @@ enum Mode {
     Normal,
     Editing,
     Exiting,
+    ClosingBuffer,
+    Search,
+    Command,
+    Replace,
+    Visual {
+        linewise: bool,
+    },
+}
+#[derive(Default)]
+enum ReplaceStage {
+    #[default]
+    Pattern,
+    Replacement,
+    Confirm,
+}
+#[derive(Default)]
+struct ReplaceState {
+    pattern: String,
+    replacement: String,
+    stage: ReplaceStage,
+    regex: Option<Regex>,
+    current_match: Option<RegexMatch>,
+    replaced: usize,
+    // where the cursor was when the replace began, which it comes back round to from the top
+    origin: usize,
+    wrapped: bool,
+}
+enum FocusDirection {
+    Left,
+    Down,
+    Up,
+    Right,
 }
 
-impl<T: TextRepresentation> App<T> {
-    pub fn new(
-        starting_string: String,
-        text_representation: T,
-        initial_window_width: usize,
-        initial_window_height: usize,
-    ) -> Self {
-        let window_height = initial_window_height - 5;
-        let window_width = initial_window_width - 10;
-        let lines_text_editor = LinesGapBuffer::new(&starting_string, window_width);
+impl<T: TextRepresentation + From<String>> App<T> {
+    pub fn new(initial_window_width: usize, initial_window_height: usize) -> Self {
+        let (window_width, window_height) =
+            window_size(initial_window_width, initial_window_height);
 
         Self {
-            text_representation,
-            lines_text_editor,
-            row_number: 0,
-            column_number: 0,
-            index: 0,
+            buffers: vec![],
+            windows: vec![],
+            current_window: 0,
+            layout: Split::Window(0),
+            pending_window_command: false,
+            search_query: String::new(),
+            search_origin: None,
+            command_line: CommandLine::default(),
+            replace: ReplaceState::default(),
+            vi_parser: CommandParser::default(),
+            registers: Registers::default(),
+            active_register: None,
+            pending_register_insert: false,
+            clipboard: None,
+            last_change: None,
+            insert_recording: None,
+            selection_anchor: 0,
+            pending_text_object: false,
+            show_buffer_list: false,
             window_height,
             window_width,
-            cursor_up_and_down_column_position_locked: false,
-            global_up_and_down_column_position: 0,
-            undo_line_commands: vec![],
-            redo_line_commands: vec![],
             exit: false,
             mode: Mode::default(),
             wrapping: true,
-            page_start: 0,
-            page_index: 0,
-        }
-    }
-    fn execute_line_command<C: TextEditorLineCommand + 'static>(&mut self, command: C) {
-        command.execute(LineCommandContext::new(
-            &mut self.lines_text_editor,
-            &self.text_representation,
-        ));
-        let contents = format!(
-            "the line gap buffer \n {:#?} and the start is {} and ending is {}",
-            self.lines_text_editor.buffer(),
-            self.lines_text_editor.starting_of_gap(),
-            self.lines_text_editor.ending_of_gap()
+            wrap_at_words: true,
+            hanging_indent: false,
+            status_segments: DEFAULT_SEGMENTS.to_vec(),
+            status_message: String::new(),
+        }
+    }
+    // copies are also handed to the clipboard, such as the OSC 52 one of the terminal
+    pub fn set_clipboard(&mut self, clipboard: Box<dyn Clipboard>) {
+        self.clipboard = Some(clipboard);
+    }
+    pub fn open_buffer(
+        &mut self,
+        file_path: String,
+        starting_string: String,
+        text_representation: T,
+    ) {
+        let mut buffer = Buffer::new(
+            file_path,
+            starting_string,
+            text_representation,
+            self.window_width,
+            self.window_height,
         );
-        fs::write("log2.txt", contents).unwrap();
-        self.undo_line_commands.push(Box::new(command));
-    }
-    fn redo(&mut self) {
-        if let Some(new_index) = self.text_representation.redo() {
-            if let Some(last_line_command) = self.redo_line_commands.pop() {
-                last_line_command.execute(LineCommandContext::new(
-                    &mut self.lines_text_editor,
-                    &self.text_representation,
-                ));
-                self.undo_line_commands.push(last_line_command);
-            }
-            let (row, column) = self
-                .lines_text_editor
-                .find_where_rope_index_fits_for_current_page(
-                    self.page_start,
-                    new_index,
-                    self.page_index,
-                );
-            if row == 0 && column == 0 {
-                self.column_number = 0;
-                self.column_number = 0;
-                self.index = 0;
-                return;
+        buffer.set_wrap_style(self.wrapping, self.wrap_at_words, self.hanging_indent);
+        self.buffers.push(buffer);
+        if self.windows.is_empty() {
+            self.windows.push(Window::default());
+            self.relayout();
+        }
+    }
+    pub fn buffers(&self) -> &[Buffer<T>] {
+        &self.buffers
+    }
+    pub fn windows(&self) -> &[Window] {
+        &self.windows
+    }
+    pub fn current_buffer_index(&self) -> usize {
+        self.windows[self.current_window].buffer
+    }
+    fn buffer(&self) -> &Buffer<T> {
+        &self.buffers[self.current_buffer_index()]
+    }
+    fn buffer_mut(&mut self) -> &mut Buffer<T> {
+        let current_buffer = self.current_buffer_index();
+        &mut self.buffers[current_buffer]
+    }
+    pub fn is_modified(&self) -> bool {
+        self.buffers.iter().any(|buffer| buffer.is_modified())
+    }
+    pub fn next_buffer(&mut self) {
+        if !self.buffers.is_empty() {
+            let next = (self.current_buffer_index() + 1) % self.buffers.len();
+            self.show_buffer_in_current_window(next);
+        }
+    }
+    pub fn previous_buffer(&mut self) {
+        if !self.buffers.is_empty() {
+            let previous =
+                (self.current_buffer_index() + self.buffers.len() - 1) % self.buffers.len();
+            self.show_buffer_in_current_window(previous);
+        }
+    }
+    fn show_buffer_in_current_window(&mut self, buffer: usize) {
+        self.windows[self.current_window] = Window::new(buffer, self.buffers[buffer].viewport());
+        self.relayout();
+        self.load_window_viewport();
+    }
+    pub fn close_buffer(&mut self) {
+        if self.buffers.is_empty() {
+            return;
+        }
+        let closed_buffer = self.current_buffer_index();
+        let closed = self.buffers.remove(closed_buffer);
+        self.status_message = format!("closed {}", closed.file_path());
+        if self.buffers.is_empty() {
+            self.exit = true;
+            return;
+        }
+        for window in self.windows.iter_mut() {
+            if window.buffer == closed_buffer {
+                window.buffer = closed_buffer.min(self.buffers.len() - 1);
+                window.viewport = self.buffers[window.buffer].viewport();
+            } else if window.buffer > closed_buffer {
+                window.buffer -= 1;
             }
-            self.index = new_index;
-            self.row_number = row;
-            self.column_number = column;
         }
+        self.relayout();
+        self.load_window_viewport();
     }
-    fn undo(&mut self) {
-        if let Some(new_index) = self.text_representation.undo() {
-            if let Some(last_line_command) = self.undo_line_commands.pop() {
-                last_line_command.undo(LineCommandContext::new(
-                    &mut self.lines_text_editor,
-                    &self.text_representation,
-                ));
-                self.redo_line_commands.push(last_line_command);
+    fn save_and_report(&mut self) -> bool {
+        let current_buffer = self.current_buffer_index();
+        let buffer = &mut self.buffers[current_buffer];
+        match buffer.save() {
+            Ok(_) => {
+                self.status_message = format!("saved {}", buffer.file_path());
+                true
             }
-
-            let (row, column) = self
-                .lines_text_editor
-                .find_where_rope_index_fits_for_current_page(
-                    self.page_start,
-                    new_index,
-                    self.page_index,
-                );
-            if row == 0 && column == 0 {
-                self.column_number = 0;
-                self.column_number = 0;
-                self.index = 0;
-                return;
+            Err(err) => {
+                self.status_message = format!("could not save {}: {}", buffer.file_path(), err);
+                false
             }
-            self.index = new_index;
-            self.row_number = row;
-            self.column_number = column;
         }
     }
-
-    pub fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
-        while !self.exit {
-            terminal.draw(|frame| self.draw(frame))?;
-            self.handle_events()?;
+    fn start_command_line(&mut self) {
+        self.command_line.start();
+        self.mode = Mode::Command;
+    }
+    fn run_command_line(&mut self) {
+        let input = self.command_line.submit();
+        self.mode = Mode::Normal;
+        if input.trim().is_empty() {
+            return;
+        }
+        match parse(&input) {
+            Ok(command) => self.execute_ex_command(command),
+            Err(err) => self.status_message = err,
         }
-        Ok(())
     }
-    fn move_line_down(&mut self) {
-        if !self.cursor_up_and_down_column_position_locked {
-            self.global_up_and_down_column_position = self.column_number;
-            self.cursor_up_and_down_column_position_locked = true;
+    fn execute_ex_command(&mut self, command: ExCommand) {
+        match command {
+            ExCommand::Write { file_path: None } => {
+                self.save_and_report();
+            }
+            ExCommand::Write {
+                file_path: Some(file_path),
+            } => {
+                self.status_message = match self.buffer().write_to(&file_path) {
+                    Ok(_) => format!("wrote {}", file_path),
+                    Err(err) => format!("could not write {}: {}", file_path, err),
+                };
+            }
+            ExCommand::Quit { force } => {
+                if force || !self.is_modified() {
+                    self.exit = true;
+                } else {
+                    self.status_message =
+                        "there are unsaved changes, :wq saves them and :q! drops them".to_string();
+                }
+            }
+            ExCommand::WriteQuit => {
+                if self.save_all_and_report() {
+                    self.exit = true;
+                }
+            }
+            ExCommand::Edit { file_path } => self.open_file(file_path),
+            ExCommand::Goto { line, column } => {
+                let line = line.map_or(self.buffer().logical_line(), |line| line.saturating_sub(1));
+                let column = column.map_or(0, |column| column.saturating_sub(1));
+                self.buffer_mut().goto_line(line, column);
+                self.buffer_mut().unlock_cursor_column();
+            }
+            ExCommand::GotoOffset { offset } => {
+                self.buffer_mut().goto_offset(offset);
+                self.buffer_mut().unlock_cursor_column();
+            }
+            ExCommand::Set { option } => self.set_option(&option),
         }
-        match self
-            .lines_text_editor
-            .index(self.page_start + self.row_number + 1)
+    }
+    // options ending in ? are reported rather than changed
+    fn set_option(&mut self, option: &str) {
+        let mut indentation = self.buffer().indentation();
+        match option.split_once('=') {
+            Some(("tabwidth", width)) => {
+                indentation.width = width.parse().unwrap_or(indentation.width);
+            }
+            Some(("statusline", segments)) => {
+                if let Ok(segments) = parse_segments(segments) {
+                    self.status_segments = segments;
+                }
+            }
+            _ => match option {
+                "wrap" => self.wrapping = true,
+                "nowrap" => self.wrapping = false,
+                "expandtab" => indentation.hard_tabs = false,
+                "noexpandtab" => indentation.hard_tabs = true,
+                "linebreak" => self.wrap_at_words = true,
+                "nolinebreak" => self.wrap_at_words = false,
+                "breakindent" => self.hanging_indent = true,
+                "nobreakindent" => self.hanging_indent = false,
+                "wrap?" | "nowrap?" => {
+                    self.status_message = match self.wrapping {
+                        true => "wrap".to_string(),
+                        false => "nowrap".to_string(),
+                    };
+                }
+                "expandtab?" | "noexpandtab?" => {
+                    self.status_message = match indentation.hard_tabs {
+                        true => "noexpandtab".to_string(),
+                        false => "expandtab".to_string(),
+                    };
+                }
+                "statusline?" => {
+                    self.status_message =
+                        format!("statusline={}", segment_names(&self.status_segments));
+                }
+                "linebreak?" | "nolinebreak?" => {
+                    self.status_message = match self.wrap_at_words {
+                        true => "linebreak".to_string(),
+                        false => "nolinebreak".to_string(),
+                    };
+                }
+                "breakindent?" | "nobreakindent?" => {
+                    self.status_message = match self.hanging_indent {
+                        true => "breakindent".to_string(),
+                        false => "nobreakindent".to_string(),
+                    };
+                }
+                _ => {
+                    self.status_message = format!("tabwidth={}", indentation.width);
+                }
+            },
+        }
+        self.buffer_mut().set_indentation(indentation);
+        // how lines wrap is the same in every buffer
+        for buffer in self.buffers.iter_mut() {
+            buffer.set_wrap_style(self.wrapping, self.wrap_at_words, self.hanging_indent);
+        }
+    }
+    // shows the file in the current window, reading it in unless a buffer already holds it
+    fn open_file(&mut self, file_path: String) {
+        if let Some(existing) = self
+            .buffers
+            .iter()
+            .position(|buffer| buffer.file_path() == file_path)
         {
-            Some(next_line_length) => {
-                self.column_number = min(next_line_length, self.global_up_and_down_column_position);
-                if self.row_number + 1 >= self.window_height {
-                    self.scroll_one_line_down();
+            self.show_buffer_in_current_window(existing);
+            return;
+        }
+        match read_or_create_file(&file_path) {
+            Ok(contents) => {
+                let text_representation = T::from(contents.clone());
+                self.open_buffer(file_path, contents, text_representation);
+                self.show_buffer_in_current_window(self.buffers.len() - 1);
+            }
+            Err(err) => {
+                self.status_message = format!("could not open {}: {}", file_path, err);
+            }
+        }
+    }
+    fn save_all_and_report(&mut self) -> bool {
+        let mut saved = Vec::new();
+        for buffer in self
+            .buffers
+            .iter_mut()
+            .filter(|buffer| buffer.is_modified())
+        {
+            if let Err(err) = buffer.save() {
+                self.status_message = format!("could not save {}: {}", buffer.file_path(), err);
+                return false;
+            }
+            saved.push(buffer.file_path().to_string());
+        }
+        self.status_message = format!("saved {}", saved.join(", "));
+        true
+    }
+    fn editor_area(&self) -> Rect {
+        Rect::new(
+            0,
+            0,
+            (self.window_width as u16).saturating_add(LINE_NUMBERS_WIDTH),
+            self.window_height as u16,
+        )
+    }
+    // returns the line number and text areas of every window, leaving a title row per window
+    // when the screen is split
+    fn window_areas(&self, area: Rect) -> Vec<(usize, Rect, Rect)> {
+        let mut areas = Vec::with_capacity(self.windows.len());
+        self.layout.areas(area, &mut areas);
+        let with_titles = self.windows.len() > 1;
+        areas
+            .into_iter()
+            .map(|(window, window_area)| {
+                let content_area = if with_titles {
+                    Rect {
+                        y: window_area.y + 1,
+                        height: window_area.height.saturating_sub(1),
+                        ..window_area
+                    }
                 } else {
-                    self.row_number += 1;
-                }
+                    window_area
+                };
+                let text_section = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints([Constraint::Length(LINE_NUMBERS_WIDTH), Constraint::Min(1)])
+                    .split(content_area);
+                (window, text_section[0], text_section[1])
+            })
+            .collect()
+    }
+    fn relayout(&mut self) {
+        let areas = self.window_areas(self.editor_area());
+        let current_buffer = self.current_buffer_index();
+        for (window, _, text_area) in areas.iter() {
+            if *window == self.current_window {
+                self.buffers[current_buffer].set_window_height(text_area.height as usize);
             }
-            None => {
-                return;
+        }
+        for (buffer_index, buffer) in self.buffers.iter_mut().enumerate() {
+            let narrowest_width = areas
+                .iter()
+                .filter(|(window, _, _)| self.windows[*window].buffer == buffer_index)
+                .map(|(_, _, text_area)| text_area.width as usize)
+                .min();
+            if let Some(width) = narrowest_width
+                && width.max(1) != buffer.window_width()
+            {
+                buffer.reflow(width.max(1));
             }
         }
-        let length_upto_non_inclusive_current_row = self
-            .lines_text_editor
-            .length_up_to_non_inclusive_index_for_current_page(
-                self.page_start + self.row_number,
-                self.page_start,
-                self.page_index,
-            );
-        self.index = length_upto_non_inclusive_current_row + self.column_number;
-    }
-    fn scroll_one_line_down(&mut self) {
-        self.page_index += self
-            .lines_text_editor
-            .index_for_offset(self.page_start)
-            .unwrap_or_default();
-        self.page_start += 1;
-    }
-    fn scroll_one_line_up(&mut self) {
-        self.page_index = match self.lines_text_editor.index_for_offset(self.page_start - 1) {
-            Some(line_length) => self.page_index - line_length,
-            None => self.page_index,
+    }
+    // the terminal changed size, so every buffer wraps again to the width of its windows while
+    // the cursor stays on the same character and in view
+    fn resize(&mut self, terminal_width: usize, terminal_height: usize) {
+        self.store_window_viewport();
+        (self.window_width, self.window_height) = window_size(terminal_width, terminal_height);
+        self.relayout();
+        self.load_window_viewport();
+    }
+    fn window_height_of(&self, window: usize) -> usize {
+        self.window_areas(self.editor_area())
+            .into_iter()
+            .find(|(current, _, _)| *current == window)
+            .map(|(_, _, text_area)| text_area.height as usize)
+            .unwrap_or(self.window_height)
+    }
+    fn store_window_viewport(&mut self) {
+        let viewport = self.buffer().viewport();
+        self.windows[self.current_window].viewport = viewport;
+    }
+    fn load_window_viewport(&mut self) {
+        let height = self.window_height_of(self.current_window);
+        let window = self.windows[self.current_window];
+        let buffer = &mut self.buffers[window.buffer];
+        buffer.set_window_height(height);
+        let viewport = buffer.refresh_viewport(window.viewport, height);
+        buffer.set_viewport(viewport);
+    }
+    pub fn focus_window(&mut self, window: usize) {
+        if window >= self.windows.len() || window == self.current_window {
+            return;
+        }
+        self.store_window_viewport();
+        self.current_window = window;
+        self.load_window_viewport();
+    }
+    pub fn split_window(&mut self, direction: Direction) {
+        self.store_window_viewport();
+        let new_window = self.windows.len();
+        self.windows.push(self.windows[self.current_window]);
+        self.layout
+            .split_window(self.current_window, new_window, direction);
+        self.current_window = new_window;
+        self.relayout();
+        self.load_window_viewport();
+    }
+    pub fn close_window(&mut self) {
+        if self.windows.len() == 1 {
+            self.status_message = "cannot close the last window".to_string();
+            return;
+        }
+        let closed_window = self.current_window;
+        self.layout.remove_window(closed_window);
+        self.layout.renumber_after_removal(closed_window);
+        self.windows.remove(closed_window);
+        self.current_window = closed_window.min(self.windows.len() - 1);
+        self.relayout();
+        self.load_window_viewport();
+    }
+    pub fn resize_window(&mut self, change: i16) {
+        if self.layout.resize_window(self.current_window, change) {
+            self.store_window_viewport();
+            self.relayout();
+            self.load_window_viewport();
+        }
+    }
+    fn focus_window_in_direction(&mut self, direction: FocusDirection) {
+        let areas = self.window_areas(self.editor_area());
+        let Some((_, _, current)) = areas
+            .iter()
+            .find(|(window, _, _)| *window == self.current_window)
+            .copied()
+        else {
+            return;
+        };
+        let centre = |area: Rect| {
+            (
+                area.x as i32 + area.width as i32 / 2,
+                area.y as i32 + area.height as i32 / 2,
+            )
         };
-        self.page_start -= 1;
+        let (current_x, current_y) = centre(current);
+        let closest = areas
+            .iter()
+            .filter(|(window, _, area)| {
+                *window != self.current_window
+                    && match direction {
+                        FocusDirection::Left => area.right() <= current.x + LINE_NUMBERS_WIDTH,
+                        FocusDirection::Right => area.x >= current.right(),
+                        FocusDirection::Up => area.bottom() <= current.y,
+                        FocusDirection::Down => area.y >= current.bottom(),
+                    }
+            })
+            .min_by_key(|(_, _, area)| {
+                let (x, y) = centre(*area);
+                (x - current_x).abs() + (y - current_y).abs()
+            })
+            .map(|(window, _, _)| *window);
+        if let Some(window) = closest {
+            self.focus_window(window);
+        }
     }
-    fn move_line_up(&mut self) {
-        if !self.cursor_up_and_down_column_position_locked {
-            self.global_up_and_down_column_position = self.column_number;
-            self.cursor_up_and_down_column_position_locked = true;
+    fn handle_window_command(&mut self, code: KeyCode) {
+        match code {
+            KeyCode::Char('s') => self.split_window(Direction::Vertical),
+            KeyCode::Char('v') => self.split_window(Direction::Horizontal),
+            KeyCode::Char('c') | KeyCode::Char('q') => self.close_window(),
+            KeyCode::Char('w') => self.focus_window((self.current_window + 1) % self.windows.len()),
+            KeyCode::Char('W') => self
+                .focus_window((self.current_window + self.windows.len() - 1) % self.windows.len()),
+            KeyCode::Char('h') | KeyCode::Left => {
+                self.focus_window_in_direction(FocusDirection::Left)
+            }
+            KeyCode::Char('j') | KeyCode::Down => {
+                self.focus_window_in_direction(FocusDirection::Down)
+            }
+            KeyCode::Char('k') | KeyCode::Up => self.focus_window_in_direction(FocusDirection::Up),
+            KeyCode::Char('l') | KeyCode::Right => {
+                self.focus_window_in_direction(FocusDirection::Right)
+            }
+            KeyCode::Char('+') => self.resize_window(5),
+            KeyCode::Char('-') => self.resize_window(-5),
+            _ => {}
         }
-        let previous_global_row_number = if self.row_number == 0 && self.page_start == 0 {
+    }
+
+    fn start_search(&mut self) {
+        self.search_origin = Some(self.buffer().viewport());
+        self.search_query.clear();
+        self.mode = Mode::Search;
+    }
+    fn update_incremental_search(&mut self) {
+        let Some(origin) = self.search_origin else {
             return;
-        } else {
-            self.row_number + self.page_start - 1
         };
-        match self.lines_text_editor.index(previous_global_row_number) {
-            Some(previous_line_length) => {
-                self.column_number = min(
-                    previous_line_length,
-                    self.global_up_and_down_column_position,
-                );
+        if self.search_query.is_empty() {
+            self.buffer_mut().set_viewport(origin);
+            return;
+        }
+        let buffer = self.buffer();
+        let found = buffer
+            .text_representation()
+            .find(&self.search_query, origin.index)
+            .or_else(|| buffer.text_representation().find(&self.search_query, 0));
+        match found {
+            Some(match_index) => {
+                self.buffer_mut().set_viewport(origin);
+                self.buffer_mut().jump_to_index(match_index);
             }
             None => {
-                self.column_number = 0;
+                self.buffer_mut().set_viewport(origin);
+                self.status_message = format!("pattern not found: {}", self.search_query);
             }
         }
-        if self.row_number == 0 {
-            self.scroll_one_line_up();
-        } else {
-            self.row_number -= 1;
+    }
+    fn cancel_search(&mut self) {
+        if let Some(origin) = self.search_origin.take() {
+            self.buffer_mut().set_viewport(origin);
         }
-        let length_upto_non_inclusive_current_row = self
-            .lines_text_editor
-            .length_up_to_non_inclusive_index_for_current_page(
-                self.page_start + self.row_number,
-                self.page_start,
-                self.page_index,
-            );
-        self.index = length_upto_non_inclusive_current_row + self.column_number;
+        self.search_query.clear();
+        self.mode = Mode::Normal;
     }
-    fn delete_char(&mut self) {
-        if self.column_number == 0 && self.row_number == 0 {
+    pub fn search_next(&mut self) {
+        if self.search_query.is_empty() {
             return;
         }
-        let final_index = self
-            .text_representation
-            .delete(1, self.index.saturating_sub(1));
-        let mut count_to_offset = 0;
-        if self.column_number == 0 && self.row_number > 0 {
-            count_to_offset = self
-                .lines_text_editor
-                .index(self.page_start + self.row_number)
-                .unwrap_or_default();
-            self.execute_line_command(MergeLineCommand::new(
-                self.page_start + self.row_number,
-                count_to_offset,
-            ));
-        } else {
-            let (starting, ending, should_offset) = self
-                .lines_text_editor
-                .find_offsets_for_line(self.page_start + self.row_number);
-            self.execute_line_command(RemoveFromLineCommand::new(
-                self.page_start + self.row_number,
-                1,
-                (starting, ending),
-                should_offset,
-            ));
+        let buffer = self.buffer();
+        let text_representation = buffer.text_representation();
+        let found = text_representation
+            .find(&self.search_query, buffer.index() + 1)
+            .or_else(|| text_representation.find(&self.search_query, 0));
+        match found {
+            Some(match_index) => self.buffer_mut().jump_to_index(match_index),
+            None => self.status_message = format!("pattern not found: {}", self.search_query),
         }
+    }
+    pub fn search_previous(&mut self) {
+        if self.search_query.is_empty() {
+            return;
+        }
+        let buffer = self.buffer();
+        let text_representation = buffer.text_representation();
+        let found = text_representation
+            .rfind(&self.search_query, buffer.index())
+            .or_else(|| {
+                text_representation.rfind(&self.search_query, text_representation.length())
+            });
+        match found {
+            Some(match_index) => self.buffer_mut().jump_to_index(match_index),
+            None => self.status_message = format!("pattern not found: {}", self.search_query),
+        }
+    }
+    fn search_highlights(
+        &self,
+        buffer: &Buffer<T>,
+        viewport: Viewport,
+        height: usize,
+        is_current_window: bool,
+    ) -> Vec<(Range<usize>, Style)> {
+        if self.search_query.is_empty() {
+            return vec![];
+        }
+        let pattern_length = self.search_query.graphemes(true).count();
+        let page_end = buffer
+            .lines_text_editor()
+            .length_up_to_non_inclusive_index_for_current_page(
+                viewport.page_start + height,
+                viewport.page_start,
+                viewport.page_index,
+            );
+        buffer
+            .text_representation()
+            .find_all(&self.search_query, (viewport.page_index, page_end))
+            .into_iter()
+            .map(|match_index| {
+                let style = if is_current_window && match_index == viewport.index {
+                    Style::default()
+                        .fg(Color::Black)
+                        .bg(Color::LightYellow)
+                        .add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(Color::Black).bg(Color::Yellow)
+                };
+                (match_index..match_index + pattern_length, style)
+            })
+            .collect()
+    }
 
-        self.move_cursor_left(count_to_offset, final_index);
-    }
-    fn add_char(&mut self, value: char) {
-        self.text_representation.display_structure();
-        let final_index = self
-            .text_representation
-            .insert(value.to_string(), self.index);
-        let offsets = self
-            .lines_text_editor
-            .find_offsets_for_line(self.page_start + self.row_number);
-        let content_to_be_logged = format!("bounds are {} {}", offsets.0, offsets.1);
-
-        fs::write("log.txt", content_to_be_logged).unwrap();
-        let (starting, ending, should_offset) = self
-            .lines_text_editor
-            .find_offsets_for_line(self.page_start + self.row_number);
-        self.execute_line_command(InsertIntoLineCommand::new(
-            self.page_start + self.row_number,
-            1,
-            (starting, ending),
-            should_offset,
-        ));
-        self.move_cursor_right(final_index);
-    }
-    fn paste(&mut self, value: String) {
-        let length_of_paste_content = value.graphemes(true).count();
-        let final_index = self.text_representation.insert(value, self.index);
-        self.move_right_due_to_paste(length_of_paste_content, final_index);
-    }
-    fn move_right_due_to_paste(&mut self, length: usize, final_index: usize) {
-        self.index = final_index;
-        let (starting, ending, should_offset) = self
-            .lines_text_editor
-            .find_offsets_for_line(self.page_start + self.row_number);
-        self.execute_line_command(InsertIntoLineCommand::new(
-            self.page_start + self.row_number,
-            length,
-            (starting, ending),
-            should_offset,
-        ));
-        let (row, column) = self
-            .lines_text_editor
-            .find_where_rope_index_fits_for_current_page(
-                self.page_start,
-                final_index,
-                self.page_index,
+    fn start_replace(&mut self) {
+        self.replace = ReplaceState::default();
+        self.mode = Mode::Replace;
+    }
+    fn confirm_replace_pattern(&mut self) {
+        match Regex::new(&self.replace.pattern) {
+            Ok(regex) => {
+                self.replace.regex = Some(regex);
+                self.replace.stage = ReplaceStage::Replacement;
+            }
+            Err(error) => {
+                self.status_message = format!("invalid pattern: {}", error);
+            }
+        }
+    }
+    fn find_regex_from(&self, from: usize, to: usize) -> Option<RegexMatch> {
+        let regex = self.replace.regex.as_ref()?;
+        self.buffer()
+            .text_representation()
+            .find_regex(regex, &self.replace.replacement, (from, to), 1)
+            .pop()
+    }
+    // moves on to the next match at or after `from`, wrapping around to the top of the file the
+    // way search does and finishing the replace once it is back where it began
+    fn advance_replace(&mut self, from: usize) {
+        let found = match self.replace.wrapped {
+            true => self.find_regex_from(from, self.replace.origin),
+            false => match self.find_regex_from(from, usize::MAX) {
+                Some(regex_match) => Some(regex_match),
+                None => {
+                    self.replace.wrapped = true;
+                    self.find_regex_from(0, self.replace.origin)
+                }
+            },
+        };
+        match found {
+            Some(regex_match) => {
+                self.buffer_mut().jump_to_index(regex_match.start);
+                self.replace.current_match = Some(regex_match);
+                self.replace.stage = ReplaceStage::Confirm;
+            }
+            None => self.finish_replace(),
+        }
+    }
+    fn replace_current(&mut self) {
+        let Some(regex_match) = self.replace.current_match.take() else {
+            return;
+        };
+        let next_from = regex_match.start
+            + regex_match.replacement.graphemes(true).count()
+            + usize::from(regex_match.length == 0);
+        // past the wrap the matches are before where the replace began, which they move
+        if self.replace.wrapped {
+            self.replace.origin = (self.replace.origin
+                + regex_match.replacement.graphemes(true).count())
+            .saturating_sub(regex_match.length);
+        }
+        self.buffer_mut().replace(vec![regex_match]);
+        self.replace.replaced += 1;
+        self.advance_replace(next_from);
+    }
+    fn skip_current(&mut self) {
+        let Some(regex_match) = self.replace.current_match.take() else {
+            return;
+        };
+        self.advance_replace(regex_match.start + regex_match.length.max(1));
+    }
+    fn replace_remaining(&mut self) {
+        let (Some(regex), Some(regex_match)) = (
+            self.replace.regex.as_ref(),
+            self.replace.current_match.take(),
+        ) else {
+            return;
+        };
+        let text_representation = self.buffer().text_representation();
+        let find = |bounds| {
+            text_representation.find_regex(regex, &self.replace.replacement, bounds, usize::MAX)
+        };
+        let matches = match self.replace.wrapped {
+            true => find((regex_match.start, self.replace.origin)),
+            false => {
+                let after = find((regex_match.start, usize::MAX));
+                // the matches above where the replace began, short of any that run into the
+                // first match below it
+                let mut matches: Vec<RegexMatch> = find((0, self.replace.origin))
+                    .into_iter()
+                    .filter(|before| before.start + before.length <= regex_match.start)
+                    .collect();
+                matches.extend(after);
+                matches
+            }
+        };
+        self.replace.replaced += matches.len();
+        // all of the remaining matches go in as a single undo step
+        self.buffer_mut().replace(matches);
+        self.finish_replace();
+    }
+    fn finish_replace(&mut self) {
+        self.status_message = format!(
+            "replaced {} occurrence(s) of {}",
+            self.replace.replaced, self.replace.pattern
+        );
+        self.replace.current_match = None;
+        self.mode = Mode::Normal;
+    }
+    fn replace_highlights(
+        &self,
+        buffer: &Buffer<T>,
+        viewport: Viewport,
+        height: usize,
+    ) -> Vec<(Range<usize>, Style)> {
+        let (Mode::Replace, Some(regex), Some(current_match)) = (
+            &self.mode,
+            self.replace.regex.as_ref(),
+            self.replace.current_match.as_ref(),
+        ) else {
+            return vec![];
+        };
+        let page_end = buffer
+            .lines_text_editor()
+            .length_up_to_non_inclusive_index_for_current_page(
+                viewport.page_start + height,
+                viewport.page_start,
+                viewport.page_index,
             );
-        self.column_number = column;
-        self.row_number = row;
-    }
-
-    fn jump_to_new_line(&mut self) {
-        let final_index = self
-            .text_representation
-            .insert("\n".to_string(), self.index);
-        let current_line_length = self
-            .lines_text_editor
-            .index(self.row_number)
-            .unwrap_or_default();
-        if self.column_number < current_line_length {
-            self.execute_line_command(SplitLineCommand::new(self.row_number, self.column_number));
-            self.index = final_index;
-        } else {
-            self.index = final_index;
-            self.execute_line_command(AddLineCommand::new(self.row_number + 1));
+        buffer
+            .text_representation()
+            .find_regex(regex, "", (viewport.page_index, page_end), usize::MAX)
+            .into_iter()
+            .map(|regex_match| {
+                let style = if regex_match.start == current_match.start {
+                    Style::default()
+                        .fg(Color::Black)
+                        .bg(Color::LightMagenta)
+                        .add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default().fg(Color::Black).bg(Color::Magenta)
+                };
+                (
+                    regex_match.start..regex_match.start + regex_match.length,
+                    style,
+                )
+            })
+            .collect()
+    }
+
+    // highlights whatever each window is about to show, so that drawing can stay read only
+    fn refresh_highlighting(&mut self) {
+        self.store_window_viewport();
+        for window in self.windows.iter() {
+            let buffer = &mut self.buffers[window.buffer];
+            let (row, _) = buffer
+                .lines_text_editor()
+                .find_where_rope_index_fits(window.viewport.index);
+            buffer.refresh_highlighting(row.max(window.viewport.page_start) + self.window_height);
         }
-        self.move_cursor_down();
     }
 
-    fn move_cursor_left(&mut self, offset: usize, final_index: usize) {
-        self.index = final_index;
-        if self.column_number == 0 {
-            if self.row_number > 0 {
-                self.column_number = self
-                    .lines_text_editor
-                    .index(self.page_start + self.row_number - 1)
-                    .unwrap_or_default()
-                    .saturating_sub(offset);
-                self.row_number -= 1;
-            } else if self.page_start > 0 {
-                self.scroll_one_line_up();
+    // runs a Normal mode command, `replay` holding the text typed after it when dot-repeating
+    fn execute_vi_command(&mut self, command: ViCommand, replay: Option<&str>) {
+        let index = self.buffer().index();
+        match command {
+            ViCommand::Move {
+                motion: Motion::Up,
+                count,
+            } => {
+                for _ in 0..count.unwrap_or(1) {
+                    self.buffer_mut().move_line_up();
+                }
+            }
+            ViCommand::Move {
+                motion: Motion::Down,
+                count,
+            } => {
+                for _ in 0..count.unwrap_or(1) {
+                    self.buffer_mut().move_line_down();
+                }
+            }
+            ViCommand::Move {
+                motion: motion @ (Motion::FirstLine | Motion::LastLine),
+                count,
+            } => {
+                let target =
+                    motion_target(self.buffer().text_representation(), index, motion, count);
+                self.buffer_mut().goto_offset(target);
+            }
+            ViCommand::Move { motion, count } => {
+                let target =
+                    motion_target(self.buffer().text_representation(), index, motion, count);
+                self.buffer_mut().jump_to_index(target);
+            }
+            ViCommand::Operate {
+                operator,
+                motion,
+                count,
+            } => {
+                let (range, linewise) = operator_range(
+                    self.buffer().text_representation(),
+                    index,
+                    operator,
+                    motion,
+                    count,
+                );
+                self.apply_operator(command, operator, range, linewise, replay);
+            }
+            ViCommand::OperateOnLines { operator, count } => {
+                let text_representation = self.buffer().text_representation();
+                let last = motion_target(text_representation, index, Motion::Down, Some(count - 1));
+                let range = line_range(text_representation, index, last);
+                self.apply_operator(command, operator, range, true, replay);
+            }
+            ViCommand::DeleteChars { count } => {
+                let end = motion_target(
+                    self.buffer().text_representation(),
+                    index,
+                    Motion::Right,
+                    Some(count),
+                );
+                if end > index {
+                    self.apply_operator(command, Operator::Delete, index..end, false, replay);
+                }
+            }
+            ViCommand::Insert { after } => {
+                if after {
+                    let target = motion_target(
+                        self.buffer().text_representation(),
+                        index,
+                        Motion::Right,
+                        None,
+                    );
+                    self.buffer_mut().jump_to_index(target);
+                }
+                self.begin_insert(command, replay);
+            }
+            ViCommand::Put { before, count } => self.put(before, count),
+            ViCommand::Undo { count } => {
+                for _ in 0..count {
+                    self.buffer_mut().undo();
+                }
+            }
+            ViCommand::Redo { count } => {
+                for _ in 0..count {
+                    self.buffer_mut().redo();
+                }
+            }
+            ViCommand::Repeat { count } => {
+                if let Some((last_command, inserted)) = self.last_change.clone() {
+                    let last_command = match count {
+                        Some(count) => last_command.with_count(count),
+                        None => last_command,
+                    };
+                    self.execute_vi_command(last_command, Some(inserted.as_deref().unwrap_or("")));
+                }
+                return;
+            }
+        }
+        if let Mode::Normal = self.mode {
+            self.keep_cursor_off_line_break();
+        }
+        self.buffer_mut().unlock_cursor_column();
+        // changes that go on into Editing mode are recorded once the insert ends
+        if command.is_change() && replay.is_none() && self.insert_recording.is_none() {
+            self.last_change = Some((command, None));
+        }
+    }
+    fn apply_operator(
+        &mut self,
+        command: ViCommand,
+        operator: Operator,
+        range: Range<usize>,
+        linewise: bool,
+        replay: Option<&str>,
+    ) {
+        let length = self.buffer().text_representation().length();
+        let ends_with_newline = linewise && range.end > range.start && {
+            let mut reader = GraphemeReader::new(self.buffer().text_representation());
+            matches!(reader.get(range.end - 1), Some("\n" | "\r\n"))
+        };
+        match operator {
+            Operator::Yank => {
+                self.store_register(
+                    self.buffer().text_in(range.clone()),
+                    linewise,
+                    RegisterWrite::Yank,
+                );
+                self.buffer_mut().jump_to_index(range.start);
+            }
+            Operator::Delete => {
+                // the last line takes the newline before it along, leaving no empty line behind
+                let range = if linewise && !ends_with_newline && range.end == length {
+                    range.start.saturating_sub(1)..range.end
+                } else {
+                    range
+                };
+                let cut_content = self.delete_range(range);
+                let cut_content = match linewise && !ends_with_newline {
+                    true => cut_content.trim_start_matches('\n').to_string(),
+                    false => cut_content,
+                };
+                self.store_register(cut_content, linewise, RegisterWrite::Delete);
+                if linewise {
+                    let mut reader = GraphemeReader::new(self.buffer().text_representation());
+                    let start = line_start(&mut reader, self.buffer().index());
+                    self.buffer_mut().jump_to_index(start);
+                }
+            }
+            Operator::Change => {
+                // changing lines keeps an empty line to type into
+                let range = match ends_with_newline {
+                    true => range.start..range.end - 1,
+                    false => range,
+                };
+                let cut_content = self.delete_range(range);
+                self.store_register(cut_content, linewise, RegisterWrite::Delete);
+                self.begin_insert(command, replay);
             }
-        } else {
-            let cursor_moved_left = self.column_number - 1;
-            self.column_number = cursor_moved_left;
         }
     }
-    fn move_cursor_down(&mut self) {
-        self.column_number = 0;
-        let cursor_moved_down = self.row_number.saturating_add(1);
-        self.row_number = cursor_moved_down;
+    fn start_visual(&mut self, linewise: bool) {
+        self.selection_anchor = self.buffer().index();
+        self.mode = Mode::Visual { linewise };
     }
-
-    fn move_cursor_right(&mut self, final_index: usize) {
-        if self.column_number
-            == self
-                .lines_text_editor
-                .index(self.row_number)
-                .unwrap_or_default()
+    // the selected text and whether it is made of whole lines, the cursor grapheme included
+    fn selection(&self) -> Option<(Range<usize>, bool)> {
+        let Mode::Visual { linewise } = self.mode else {
+            return None;
+        };
+        let text_representation = self.buffer().text_representation();
+        let index = self.buffer().index();
+        let first = index.min(self.selection_anchor);
+        let last = index.max(self.selection_anchor);
+        let range = match linewise {
+            true => line_range(text_representation, first, last),
+            false => first..(last + 1).min(text_representation.length()),
+        };
+        Some((range, linewise))
+    }
+    // `iw` in Visual mode selects the word under the cursor
+    fn select_word(&mut self) {
+        let range = word_at(self.buffer().text_representation(), self.buffer().index());
+        if range.is_empty() {
+            return;
+        }
+        self.selection_anchor = range.start;
+        self.buffer_mut().jump_to_index(range.end - 1);
+    }
+    fn move_by_word(&mut self, forward: bool) {
+        let text_representation = self.buffer().text_representation();
+        let index = self.buffer().index();
+        let target = match (forward, &self.mode) {
+            // the selection takes in the cursor grapheme, so it stops just before the next word
+            (true, Mode::Visual { .. }) => next_word_boundary(text_representation, index + 1)
+                .saturating_sub(1)
+                .max(index),
+            (true, _) => next_word_boundary(text_representation, index),
+            (false, _) => previous_word_boundary(text_representation, index),
+        };
+        self.buffer_mut().jump_to_index(target);
+        self.buffer_mut().unlock_cursor_column();
+    }
+    fn delete_word(&mut self, forward: bool) {
+        let text_representation = self.buffer().text_representation();
+        let index = self.buffer().index();
+        let range = match forward {
+            true => index..next_word_boundary(text_representation, index),
+            false => previous_word_boundary(text_representation, index)..index,
+        };
+        if range.is_empty() {
+            return;
+        }
+        let deleted = range.len();
+        self.delete_range(range);
+        self.buffer_mut().unlock_cursor_column();
+        if !forward {
+            self.record_insert(|inserted| {
+                for _ in 0..deleted {
+                    inserted.pop();
+                }
+            });
+        }
+    }
+    // Tab and Shift+Tab shift the selected lines, or the line of the cursor outside of Visual mode
+    fn indent(&mut self, outdent: bool) {
+        let lines = match self.selection() {
+            Some((range, _)) => {
+                let buffer = self.buffer();
+                buffer.logical_line_at(range.start)
+                    ..buffer.logical_line_at(range.end.saturating_sub(1).max(range.start)) + 1
+            }
+            None => {
+                let line = self.buffer().logical_line();
+                line..line + 1
+            }
+        };
+        self.buffer_mut().indent_lines(lines, outdent);
+        self.buffer_mut().unlock_cursor_column();
+    }
+    fn swap_selection_ends(&mut self) {
+        let index = self.buffer().index();
+        let anchor = self.selection_anchor;
+        self.buffer_mut().jump_to_index(anchor);
+        self.selection_anchor = index;
+    }
+    fn operate_on_selection(&mut self, operator: Operator) {
+        let Some((range, linewise)) = self.selection() else {
+            return;
+        };
+        self.active_register = self.vi_parser.register();
+        self.vi_parser.reset();
+        self.mode = Mode::Normal;
+        // dot-repeat acts on the same amount of text from wherever the cursor is then
+        let command = match linewise {
+            true => {
+                let mut reader = GraphemeReader::new(self.buffer().text_representation());
+                let line_count = (range.start..range.end.saturating_sub(1))
+                    .filter(|index| matches!(reader.get(*index), Some("\n" | "\r\n")))
+                    .count()
+                    + 1;
+                ViCommand::OperateOnLines {
+                    operator,
+                    count: line_count,
+                }
+            }
+            false => ViCommand::Operate {
+                operator,
+                motion: Motion::Right,
+                count: Some(range.len()),
+            },
+        };
+        self.buffer_mut().jump_to_index(range.start);
+        self.apply_operator(command, operator, range, linewise, None);
+        self.buffer_mut().unlock_cursor_column();
+        self.active_register = None;
+        if operator == Operator::Delete {
+            self.last_change = Some((command, None));
+        }
+    }
+    fn bracket_highlights(&self) -> Vec<(Range<usize>, Style)> {
+        let style = Style::default()
+            .bg(Color::DarkGray)
+            .add_modifier(Modifier::BOLD);
+        match self.buffer().visible_bracket_pair() {
+            Some((start, end)) => vec![(start..start + 1, style), (end..end + 1, style)],
+            None => vec![],
+        }
+    }
+    fn selection_highlights(&self) -> Vec<(Range<usize>, Style)> {
+        match self.selection() {
+            Some((range, _)) => vec![(range, Style::default().fg(Color::White).bg(Color::Blue))],
+            None => vec![],
+        }
+    }
+    // removes a range of the current buffer, however many lines it spans, as a single undo step
+    pub fn delete_range(&mut self, range: Range<usize>) -> String {
+        self.buffer_mut().delete_range(range)
+    }
+    fn store_register(&mut self, text: String, linewise: bool, write: RegisterWrite) {
+        let text = match linewise && !text.ends_with('\n') {
+            true => text + "\n",
+            false => text,
+        };
+        let name = self.active_register;
+        if Registers::reaches_clipboard(name, write)
+            && let Some(clipboard) = self.clipboard.as_mut()
+            && let Err(err) = clipboard.copy(&text)
         {
-            if self.lines_text_editor.index(self.row_number + 1).is_some() {
-                self.row_number += 1;
-                self.column_number = 0;
+            self.status_message = format!("could not copy to the clipboard: {}", err);
+        }
+        self.registers
+            .store(name, Register { text, linewise }, write);
+    }
+    fn register_or_report(&mut self) -> Option<Register> {
+        let register = self.registers.get(self.active_register).cloned();
+        if register.is_none() {
+            self.status_message = match self.active_register {
+                Some(name) => format!("register {} is empty", name),
+                None => "nothing to put".to_string(),
+            };
+        }
+        register
+    }
+    // Ctrl+R in Editing mode types out the contents of the register named next
+    fn insert_register(&mut self, name: char) {
+        self.active_register = Some(name);
+        let register = self.register_or_report();
+        self.active_register = None;
+        if let Some(register) = register {
+            let index = self.buffer().index();
+            self.buffer_mut().insert_text(index, &register.text);
+            self.buffer_mut().unlock_cursor_column();
+            self.record_insert(|inserted| inserted.push_str(&register.text));
+        }
+    }
+    fn begin_insert(&mut self, command: ViCommand, replay: Option<&str>) {
+        match replay {
+            Some(text) => {
+                let index = self.buffer().index();
+                self.buffer_mut().insert_text(index, text);
+            }
+            None => {
+                self.insert_recording = Some((command, String::new()));
+                self.mode = Mode::Editing;
             }
-            self.index = final_index;
+        }
+    }
+    // leaving Editing mode steps back onto the last grapheme typed, as in vi
+    fn end_insert(&mut self) {
+        if let Some((command, inserted)) = self.insert_recording.take() {
+            self.last_change = Some((command, Some(inserted)));
+        }
+        let index = self.buffer().index();
+        let mut reader = GraphemeReader::new(self.buffer().text_representation());
+        if index > line_start(&mut reader, index) {
+            self.buffer_mut().jump_to_index(index - 1);
+        }
+    }
+    // Normal mode keeps the cursor on the graphemes of a line, short of the break that ends it
+    fn keep_cursor_off_line_break(&mut self) {
+        let index = self.buffer().index();
+        let mut reader = GraphemeReader::new(self.buffer().text_representation());
+        let start = line_start(&mut reader, index);
+        let end = line_end(&mut reader, index);
+        if index >= end && end > start {
+            self.buffer_mut().jump_to_index(end - 1);
+        }
+    }
+    fn record_insert(&mut self, change: impl FnOnce(&mut String)) {
+        if let Some((_, inserted)) = &mut self.insert_recording {
+            change(inserted);
+        }
+    }
+    fn put(&mut self, before: bool, count: usize) {
+        let Some(register) = self.register_or_report() else {
+            return;
+        };
+        let text = register.text.repeat(count);
+        let index = self.buffer().index();
+        let mut reader = GraphemeReader::new(self.buffer().text_representation());
+        let length = reader.len();
+        if register.linewise {
+            let start = line_start(&mut reader, index);
+            let end = line_end(&mut reader, index);
+            // the cursor goes to the start of the first line put in
+            let (position, text, line) = match (before, end < length) {
+                (true, _) => (start, text, start),
+                (false, true) => (end + 1, text, end + 1),
+                // after the last line, which has no newline of its own
+                (false, false) => (
+                    length,
+                    format!("\n{}", text.trim_end_matches('\n')),
+                    length + 1,
+                ),
+            };
+            self.buffer_mut().insert_text(position, &text);
+            let new_length = self.buffer().text_representation().length();
+            self.buffer_mut().jump_to_index(line.min(new_length));
         } else {
-            self.index = final_index;
-            let cursor_moved_right = self.column_number.saturating_add(1);
-            self.column_number = cursor_moved_right;
+            let position = match before {
+                true => index,
+                false => (index + 1).min(line_end(&mut reader, index)),
+            };
+            self.buffer_mut().insert_text(position, &text);
+            let inserted_end = self.buffer().index();
+            self.buffer_mut()
+                .jump_to_index(inserted_end.saturating_sub(1).max(position));
         }
     }
 
-    fn draw(&self, frame: &mut Frame) {
+    pub fn run(&mut self, terminal: &mut DefaultTerminal) -> io::Result<()> {
+        if self.buffers.is_empty() {
+            return Ok(());
+        }
+        while !self.exit {
+            self.prepare_frame();
+            terminal.draw(|frame| self.draw(frame))?;
+            self.handle_event(event::read()?);
+        }
+        Ok(())
+    }
+    // brings highlighting and scrolling up to date with the cursor before a frame is drawn
+    pub fn prepare_frame(&mut self) {
+        self.refresh_highlighting();
+        self.buffer_mut().scroll_to_cursor();
+    }
+
+    pub fn draw(&self, frame: &mut Frame) {
         let area = frame.area();
+        let buffer = self.buffer();
         let chunks = Layout::default()
             .direction(Direction::Vertical)
             .constraints([
@@ -390,79 +1273,280 @@ impl<T: TextRepresentation> App<T> {
             .borders(Borders::RIGHT)
             .style(Style::default());
 
-        let title = Paragraph::new(Text::styled(
-            "Text editor",
-            Style::default().fg(Color::Green),
-        ))
-        .block(title_block.clone());
+        let title_text = vec![
+            Span::styled("Text editor", Style::default().fg(Color::Green)),
+            Span::styled(" | ", Style::default().fg(Color::White)),
+            Span::styled(
+                format!(
+                    "[{}/{}] ",
+                    self.current_buffer_index() + 1,
+                    self.buffers.len()
+                ),
+                Style::default().fg(Color::White),
+            ),
+            Span::styled(buffer.file_path(), Style::default().fg(Color::White)),
+            if buffer.is_modified() {
+                Span::styled(" [modified]", Style::default().fg(Color::Yellow))
+            } else {
+                Span::raw("")
+            },
+        ];
+        let title = Paragraph::new(Line::from(title_text)).block(title_block.clone());
         frame.render_widget(title, chunks[0]);
-        let text_section = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([Constraint::Length(5), Constraint::Min(1)])
-            .split(chunks[1]);
-
-        let line_numbers = Paragraph::new(self.lines_text_editor.get_line_numbers())
-            .block(line_numbers_block)
+        for (window_index, line_numbers_area, text_area) in self.window_areas(chunks[1]) {
+            let window = self.windows[window_index];
+            let window_buffer = &self.buffers[window.buffer];
+            let is_current_window = window_index == self.current_window;
+            let viewport = if is_current_window {
+                window_buffer.viewport()
+            } else {
+                window_buffer.refresh_viewport(window.viewport, text_area.height as usize)
+            };
+            if self.windows.len() > 1 {
+                let window_title_style = if is_current_window {
+                    Style::default().fg(Color::Black).bg(Color::Green)
+                } else {
+                    Style::default().fg(Color::White).bg(Color::DarkGray)
+                };
+                let window_title_area = Rect {
+                    y: text_area.y - 1,
+                    height: 1,
+                    x: line_numbers_area.x,
+                    width: line_numbers_area.width + text_area.width,
+                };
+                frame.render_widget(
+                    Paragraph::new(Line::from(format!(
+                        " {}{}",
+                        window_buffer.file_path(),
+                        if window_buffer.is_modified() {
+                            " [modified]"
+                        } else {
+                            ""
+                        }
+                    )))
+                    .style(window_title_style),
+                    window_title_area,
+                );
+            }
+            let line_numbers = Paragraph::new(
+                window_buffer
+                    .lines_text_editor()
+                    .get_line_numbers_for_current_page(
+                        viewport.page_start,
+                        text_area.height as usize,
+                    ),
+            )
+            .block(line_numbers_block.clone())
             .alignment(Alignment::Right);
-        let text_content = Paragraph::new(self.lines_text_editor.get_lines());
-        frame.render_widget(text_content, text_section[1]);
-        frame.render_widget(line_numbers, text_section[0]);
-        if let Mode::Editing = self.mode {
-            frame.set_cursor_position(Position::new(
-                // Draw the cursor at the current position in the input field.
-                // This position is can be controlled via the left and right arrow key
-                text_section[1].x + self.column_number as u16,
-                // Move one line down, from the border to the input line
-                text_section[1].y + self.row_number as u16,
-            ))
+            let mut highlights =
+                window_buffer.syntax_highlights(viewport, text_area.height as usize);
+            highlights.extend(self.search_highlights(
+                window_buffer,
+                viewport,
+                text_area.height as usize,
+                is_current_window,
+            ));
+            if is_current_window {
+                highlights.extend(self.replace_highlights(
+                    window_buffer,
+                    viewport,
+                    text_area.height as usize,
+                ));
+                highlights.extend(self.bracket_highlights());
+                highlights.extend(self.selection_highlights());
+            }
+            let text_content = Paragraph::new(
+                window_buffer
+                    .lines_text_editor()
+                    .get_lines_for_current_page(
+                        viewport.page_start,
+                        text_area.height as usize,
+                        viewport.page_index,
+                        viewport.scroll_column,
+                        &highlights,
+                    ),
+            );
+            frame.render_widget(text_content, text_area);
+            frame.render_widget(line_numbers, line_numbers_area);
+            if let Mode::Normal | Mode::Editing | Mode::Search | Mode::Replace | Mode::Visual { .. } =
+                self.mode
+                && is_current_window
+            {
+                frame.set_cursor_position(Position::new(
+                    // Draw the cursor at the current position in the input field.
+                    // This position is can be controlled via the left and right arrow key
+                    text_area.x
+                        + window_buffer
+                            .lines_text_editor()
+                            .display_column(
+                                viewport.page_start + viewport.row_number,
+                                viewport.column_number,
+                            )
+                            .saturating_sub(viewport.scroll_column)
+                            as u16,
+                    // Move one line down, from the border to the input line
+                    text_area.y + viewport.row_number as u16,
+                ))
+            }
         }
-        let footer_chunks = Layout::default()
-            .direction(Direction::Horizontal)
-            .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
-            .split(chunks[2]);
-
+        let status_text = self
+            .status_segments
+            .iter()
+            .map(|segment| segment.text(buffer))
+            .filter(|text| !text.is_empty())
+            .collect::<Vec<_>>()
+            .join(" | ");
         let current_navigation_text = vec![
             // The first half of the text
             match self.mode {
-                Mode::Normal => Span::styled("Normal Mode", Style::default().fg(Color::Green)),
-                Mode::Editing => Span::styled("Editing Mode", Style::default().fg(Color::Yellow)),
+                Mode::Normal => Span::styled(
+                    format!("Normal Mode {}", self.vi_parser.pending_keys()),
+                    Style::default().fg(Color::Green),
+                ),
+                Mode::Editing => Span::styled(
+                    match self.pending_register_insert {
+                        true => "Editing Mode ^R",
+                        false => "Editing Mode",
+                    },
+                    Style::default().fg(Color::Yellow),
+                ),
                 Mode::Exiting => Span::styled("Exiting", Style::default().fg(Color::LightRed)),
+                Mode::ClosingBuffer => {
+                    Span::styled("Closing buffer", Style::default().fg(Color::LightRed))
+                }
+                Mode::Search => Span::styled(
+                    format!("/{}", self.search_query),
+                    Style::default().fg(Color::LightBlue),
+                ),
+                // the prompt is drawn over the whole footer instead
+                Mode::Command => Span::raw(""),
+                Mode::Replace => Span::styled(
+                    match self.replace.stage {
+                        ReplaceStage::Pattern => format!("replace: {}", self.replace.pattern),
+                        ReplaceStage::Replacement => format!(
+                            "replace {} with: {}",
+                            self.replace.pattern, self.replace.replacement
+                        ),
+                        ReplaceStage::Confirm => format!(
+                            "replace {} with {}?",
+                            self.replace.pattern, self.replace.replacement
+                        ),
+                    },
+                    Style::default().fg(Color::LightMagenta),
+                ),
+                Mode::Visual { linewise } => Span::styled(
+                    match linewise {
+                        true => format!("Visual Line {}", self.vi_parser.pending_keys()),
+                        false => format!("Visual {}", self.vi_parser.pending_keys()),
+                    },
+                    Style::default().fg(Color::LightBlue),
+                ),
             }
             .to_owned(),
             // A white divider bar to separate the two sections
             Span::styled(" | ", Style::default().fg(Color::White)),
-            // The final section of the text, with hints on what the user is editing
-            Span::styled(
-                format!(
-                    "column {} row {} index:{}",
-                    self.column_number, self.row_number, self.index
-                ),
-                Style::default().fg(Color::Green),
-            ),
+            // The final section of the text, with the segments of the status line
+            Span::styled(status_text, Style::default().fg(Color::Green)),
         ];
+        let current_navigation_text = Line::from(current_navigation_text);
+        // the status line takes the room it needs and the hints are left what remains, except
+        // that a message always keeps the room it needs or else half of the footer
+        let status_width = (current_navigation_text.width() as u16).saturating_add(2);
+        let status_width = match self.status_message.is_empty() {
+            true => status_width,
+            false => status_width.min(
+                chunks[2]
+                    .width
+                    .saturating_sub((self.status_message.width() as u16).saturating_add(2))
+                    .max(chunks[2].width / 2),
+            ),
+        };
+        let footer_chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Length(status_width), Constraint::Min(0)])
+            .split(chunks[2]);
 
-        let mode_footer = Paragraph::new(Line::from(current_navigation_text))
-            .block(Block::default().borders(Borders::ALL));
+        let mode_footer =
+            Paragraph::new(current_navigation_text).block(Block::default().borders(Borders::ALL));
         let current_keys_hint = {
             match self.mode {
-                Mode::Normal => {
-                    Span::styled("(q) to quit / (e) to edit", Style::default().fg(Color::Red))
-                }
+                Mode::Normal => Span::styled(
+                    "(q) quit / (i a) insert / (hjkl w b e 0 $ gg G %) move / (d c y x p . u) edit / (\"x) register / (v V) select / (s) save / (/ n N) search / (R) replace / (:) command / ([ ]) (B) (X) buffers / (Ctrl+W) windows",
+                    Style::default().fg(Color::Red),
+                ),
                 Mode::Editing => Span::styled(
-                    "(ESC) to go to normal mode",
+                    "(ESC) to go to normal mode / (Ctrl+R x) insert register",
+                    Style::default().fg(Color::Red),
+                ),
+                Mode::Exiting => Span::styled(
+                    "(y) save and quit / (n) quit / (ESC) cancel",
+                    Style::default().fg(Color::Red),
+                ),
+                Mode::ClosingBuffer => Span::styled(
+                    "(y) save and close / (n) close / (ESC) cancel",
+                    Style::default().fg(Color::Red),
+                ),
+                Mode::Search => Span::styled(
+                    "(ENTER) to keep position / (ESC) to cancel",
+                    Style::default().fg(Color::Red),
+                ),
+                Mode::Command => match self.command_line.completion_labels().len() > 1 {
+                    true => Span::styled(
+                        self.command_line.completion_labels().join(" "),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                    false => Span::styled(
+                        "(TAB) complete / (UP DOWN) history / (ENTER) run / (ESC) cancel",
+                        Style::default().fg(Color::Red),
+                    ),
+                },
+                Mode::Replace => match self.replace.stage {
+                    ReplaceStage::Pattern | ReplaceStage::Replacement => Span::styled(
+                        "(ENTER) to confirm / (ESC) to cancel / $1 ${name} for groups",
+                        Style::default().fg(Color::Red),
+                    ),
+                    ReplaceStage::Confirm => Span::styled(
+                        "(y) replace / (n) skip / (a) replace all / (q) quit",
+                        Style::default().fg(Color::Red),
+                    ),
+                },
+                Mode::Visual { .. } => Span::styled(
+                    "(hjkl w b e 0 $ gg G) extend / (d x) delete / (y) yank / (c) change / (o) other end / (ESC) cancel",
                     Style::default().fg(Color::Red),
                 ),
-                Mode::Exiting => Span::styled("(q) to quit", Style::default().fg(Color::Red)),
             }
         };
+        let current_keys_hint = if self.status_message.is_empty() {
+            current_keys_hint
+        } else {
+            Span::styled(
+                self.status_message.as_str(),
+                Style::default().fg(Color::Yellow),
+            )
+        };
 
-        let key_notes_footer = Paragraph::new(Line::from(current_keys_hint))
+        let key_notes_footer = Paragraph::new(Line::from(current_keys_hint.clone()))
             .block(Block::default().borders(Borders::ALL));
 
-        frame.render_widget(mode_footer, footer_chunks[0]);
-        frame.render_widget(key_notes_footer, footer_chunks[1]);
+        if let Mode::Command = self.mode {
+            let input = self.command_line.input();
+            let prompt = Paragraph::new(Line::from(vec![
+                Span::styled(format!(":{}", input), Style::default().fg(Color::White)),
+                Span::raw("   "),
+                current_keys_hint,
+            ]))
+            .block(Block::default().borders(Borders::ALL));
+            frame.render_widget(prompt, chunks[2]);
+            frame.set_cursor_position(Position::new(
+                chunks[2].x + 2 + input.width() as u16,
+                chunks[2].y + 1,
+            ));
+        } else {
+            frame.render_widget(mode_footer, footer_chunks[0]);
+            frame.render_widget(key_notes_footer, footer_chunks[1]);
+        }
 
-        if let Mode::Exiting = self.mode {
+        if let Mode::Exiting | Mode::ClosingBuffer = self.mode {
             // frame.render_widget(Clear, frame.area()); //this clears the entire screen and anything already drawn
             let popup_block = Block::default()
                 .title("Y/N")
@@ -470,7 +1554,16 @@ impl<T: TextRepresentation> App<T> {
                 .style(Style::default().bg(Color::DarkGray));
 
             let exit_text = Text::styled(
-                "Would you save the file? (y/n)",
+                match self.mode {
+                    Mode::ClosingBuffer => format!(
+                        "Would you save {} before closing it? (y/n)",
+                        buffer.file_path()
+                    ),
+                    _ if self.buffers.len() > 1 => {
+                        "Would you save the modified files? (y/n)".to_string()
+                    }
+                    _ => "Would you save the file? (y/n)".to_string(),
+                },
                 Style::default().fg(Color::Red),
             );
             // the `trim: false` will stop the text from being cut off when over the edge of the block
@@ -480,100 +1573,487 @@ impl<T: TextRepresentation> App<T> {
                 .wrap(Wrap { trim: false });
             frame.render_widget(exit_paragraph, exit_pop_up_area);
         }
+        if self.show_buffer_list {
+            let buffer_list_block = Block::default()
+                .title("Buffers")
+                .borders(Borders::ALL)
+                .style(Style::default().bg(Color::DarkGray));
+            let buffer_lines: Vec<Line> = self
+                .buffers
+                .iter()
+                .enumerate()
+                .map(|(position, listed_buffer)| {
+                    let marker = if position == self.current_buffer_index() {
+                        ">"
+                    } else {
+                        " "
+                    };
+                    let modified = if listed_buffer.is_modified() {
+                        " [modified]"
+                    } else {
+                        ""
+                    };
+                    let style = if position == self.current_buffer_index() {
+                        Style::default().fg(Color::Green)
+                    } else {
+                        Style::default().fg(Color::White)
+                    };
+                    Line::styled(
+                        format!(
+                            "{} {} {}{}",
+                            marker,
+                            position + 1,
+                            listed_buffer.file_path(),
+                            modified
+                        ),
+                        style,
+                    )
+                })
+                .collect();
+            let buffer_list_area = centered_rect(60, 50, area);
+            frame.render_widget(Clear, buffer_list_area);
+            frame.render_widget(
+                Paragraph::new(buffer_lines).block(buffer_list_block),
+                buffer_list_area,
+            );
+        }
     }
 
-    fn handle_events(&mut self) -> io::Result<()> {
-        match event::read()? {
+    // the page and document keys, which work the same in Normal, Editing and Visual mode
+    fn handle_navigation_key(&mut self, key: KeyEvent) -> bool {
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::PageDown => self.buffer_mut().page_down(),
+            KeyCode::PageUp => self.buffer_mut().page_up(),
+            KeyCode::Home if control => self.buffer_mut().move_to_start(),
+            KeyCode::End if control => self.buffer_mut().move_to_end(),
+            KeyCode::Home => self.buffer_mut().move_to_line_start(),
+            KeyCode::End => self.buffer_mut().move_to_line_end(),
+            _ => return false,
+        }
+        if !matches!(key.code, KeyCode::PageDown | KeyCode::PageUp) {
+            self.buffer_mut().unlock_cursor_column();
+        }
+        self.vi_parser.reset();
+        true
+    }
+    pub fn handle_event(&mut self, event: Event) {
+        match event {
             Event::Key(key) => {
                 if key.kind == event::KeyEventKind::Release {
                     // Skip events that are not KeyEventKind::Press
-                    return Ok(());
+                    return;
+                }
+                self.status_message.clear();
+                if self.pending_window_command {
+                    self.pending_window_command = false;
+                    self.handle_window_command(key.code);
+                    return;
+                }
+                if self.pending_register_insert {
+                    self.pending_register_insert = false;
+                    if let KeyCode::Char(name) = key.code
+                        && Registers::is_valid_name(name)
+                    {
+                        self.insert_register(name);
+                    }
+                    return;
+                }
+                if self.show_buffer_list {
+                    self.show_buffer_list = false;
+                    if let KeyCode::Esc | KeyCode::Char('B') = key.code {
+                        return;
+                    }
+                }
+                if let Mode::Normal | Mode::Editing | Mode::Visual { .. } = self.mode
+                    && self.handle_navigation_key(key)
+                {
+                    return;
                 }
                 match self.mode {
-                    Mode::Normal => match key.code {
-                        KeyCode::Char('e') => {
-                            self.mode = Mode::Editing;
+                    Mode::Normal => match (self.vi_parser.register(), self.vi_parser.feed(key)) {
+                        (register, Parsed::Command(command)) => {
+                            self.active_register = register;
+                            self.execute_vi_command(command, None);
+                            self.active_register = None;
+                        }
+                        (_, Parsed::Pending | Parsed::Invalid) => {}
+                        (_, Parsed::Unhandled) => match key.code {
+                            KeyCode::Char('w') if key.modifiers == KeyModifiers::CONTROL => {
+                                self.pending_window_command = true;
+                            }
+                            KeyCode::Char('/') => {
+                                self.start_search();
+                            }
+                            KeyCode::Char(':') => {
+                                self.start_command_line();
+                            }
+                            KeyCode::Char('%') => {
+                                self.buffer_mut().jump_to_matching_bracket();
+                            }
+                            KeyCode::Left | KeyCode::Right
+                                if key.modifiers == KeyModifiers::CONTROL | KeyModifiers::SHIFT =>
+                            {
+                                self.start_visual(false);
+                                self.move_by_word(key.code == KeyCode::Right);
+                            }
+                            KeyCode::Left | KeyCode::Right
+                                if key.modifiers == KeyModifiers::CONTROL =>
+                            {
+                                self.move_by_word(key.code == KeyCode::Right);
+                            }
+                            KeyCode::Delete if key.modifiers == KeyModifiers::CONTROL => {
+                                self.delete_word(true);
+                            }
+                            KeyCode::Delete => {
+                                self.execute_vi_command(ViCommand::DeleteChars { count: 1 }, None);
+                            }
+                            KeyCode::Char('v') => {
+                                self.start_visual(false);
+                            }
+                            KeyCode::Char('V') => {
+                                self.start_visual(true);
+                            }
+                            KeyCode::Char('n') => {
+                                self.search_next();
+                            }
+                            KeyCode::Char('N') => {
+                                self.search_previous();
+                            }
+                            KeyCode::Char('R') => {
+                                self.start_replace();
+                            }
+                            KeyCode::Char('q') => {
+                                if self.is_modified() {
+                                    self.mode = Mode::Exiting;
+                                } else {
+                                    self.exit = true;
+                                }
+                            }
+                            KeyCode::Char('s') => {
+                                self.save_and_report();
+                            }
+                            KeyCode::Char(']') => {
+                                self.next_buffer();
+                            }
+                            KeyCode::Char('[') => {
+                                self.previous_buffer();
+                            }
+                            KeyCode::Char('B') => {
+                                self.show_buffer_list = true;
+                            }
+                            KeyCode::Char('X') => {
+                                if self.buffer().is_modified() {
+                                    self.mode = Mode::ClosingBuffer;
+                                } else {
+                                    self.close_buffer();
+                                }
+                            }
+                            _ => {}
+                        },
+                    },
+                    Mode::Visual { linewise } => {
+                        let text_object = std::mem::take(&mut self.pending_text_object);
+                        match key.code {
+                            KeyCode::Esc => {
+                                self.vi_parser.reset();
+                                self.mode = Mode::Normal;
+                            }
+                            KeyCode::Char('v') => {
+                                self.mode = match linewise {
+                                    true => Mode::Visual { linewise: false },
+                                    false => Mode::Normal,
+                                };
+                            }
+                            KeyCode::Char('V') => {
+                                self.mode = match linewise {
+                                    true => Mode::Normal,
+                                    false => Mode::Visual { linewise: true },
+                                };
+                            }
+                            KeyCode::Char('w') if text_object => {
+                                self.select_word();
+                            }
+                            KeyCode::Char('i') => {
+                                self.pending_text_object = true;
+                            }
+                            KeyCode::Left | KeyCode::Right
+                                if key.modifiers.contains(KeyModifiers::CONTROL) =>
+                            {
+                                self.move_by_word(key.code == KeyCode::Right);
+                            }
+                            KeyCode::Char('o') => {
+                                self.swap_selection_ends();
+                            }
+                            KeyCode::Char('%') => {
+                                self.buffer_mut().jump_to_matching_bracket();
+                            }
+                            KeyCode::Tab | KeyCode::BackTab => {
+                                self.indent(key.code == KeyCode::BackTab);
+                                self.vi_parser.reset();
+                                self.mode = Mode::Normal;
+                            }
+                            KeyCode::Char('d' | 'x') => {
+                                self.operate_on_selection(Operator::Delete);
+                            }
+                            KeyCode::Char('y') if key.modifiers != KeyModifiers::CONTROL => {
+                                self.operate_on_selection(Operator::Yank);
+                            }
+                            KeyCode::Char('c') => {
+                                self.operate_on_selection(Operator::Change);
+                            }
+                            _ => {
+                                if let Parsed::Command(command @ ViCommand::Move { .. }) =
+                                    self.vi_parser.feed(key)
+                                {
+                                    self.execute_vi_command(command, None);
+                                }
+                            }
+                        }
+                    }
+                    Mode::Search => match key.code {
+                        KeyCode::Enter => {
+                            self.search_origin = None;
+                            self.mode = Mode::Normal;
+                        }
+                        KeyCode::Esc => {
+                            self.cancel_search();
+                        }
+                        KeyCode::Backspace => {
+                            self.search_query.pop();
+                            self.update_incremental_search();
+                        }
+                        KeyCode::Char(value) => {
+                            self.search_query.push(value);
+                            self.update_incremental_search();
+                        }
+                        _ => {}
+                    },
+                    Mode::Command => match key.code {
+                        KeyCode::Enter => {
+                            self.run_command_line();
+                        }
+                        KeyCode::Esc => {
+                            self.mode = Mode::Normal;
+                        }
+                        // deleting past the start closes the prompt
+                        KeyCode::Backspace if self.command_line.input().is_empty() => {
+                            self.mode = Mode::Normal;
+                        }
+                        KeyCode::Backspace => {
+                            self.command_line.pop();
+                        }
+                        KeyCode::Tab => {
+                            self.command_line.complete(false);
+                        }
+                        KeyCode::BackTab => {
+                            self.command_line.complete(true);
+                        }
+                        KeyCode::Up => {
+                            self.command_line.history_previous();
+                        }
+                        KeyCode::Down => {
+                            self.command_line.history_next();
+                        }
+                        KeyCode::Char(value) => {
+                            self.command_line.push(value);
+                        }
+                        _ => {}
+                    },
+                    Mode::Replace => match self.replace.stage {
+                        ReplaceStage::Pattern | ReplaceStage::Replacement => match key.code {
+                            KeyCode::Esc => {
+                                self.mode = Mode::Normal;
+                            }
+                            KeyCode::Enter => {
+                                if let ReplaceStage::Pattern = self.replace.stage {
+                                    self.confirm_replace_pattern();
+                                } else {
+                                    self.replace.origin = self.buffer().index();
+                                    self.advance_replace(self.replace.origin);
+                                }
+                            }
+                            KeyCode::Backspace => {
+                                if let ReplaceStage::Pattern = self.replace.stage {
+                                    self.replace.pattern.pop();
+                                } else {
+                                    self.replace.replacement.pop();
+                                }
+                            }
+                            KeyCode::Char(value) => {
+                                if let ReplaceStage::Pattern = self.replace.stage {
+                                    self.replace.pattern.push(value);
+                                } else {
+                                    self.replace.replacement.push(value);
+                                }
+                            }
+                            _ => {}
+                        },
+                        ReplaceStage::Confirm => match key.code {
+                            KeyCode::Char('y') => {
+                                self.replace_current();
+                            }
+                            KeyCode::Char('n') => {
+                                self.skip_current();
+                            }
+                            KeyCode::Char('a') => {
+                                self.replace_remaining();
+                            }
+                            KeyCode::Char('q') | KeyCode::Esc => {
+                                self.finish_replace();
+                            }
+                            _ => {}
+                        },
+                    },
+                    Mode::ClosingBuffer => match key.code {
+                        KeyCode::Char('y') => {
+                            if self.save_and_report() {
+                                self.close_buffer();
+                            }
+                            self.mode = Mode::Normal;
                         }
-                        KeyCode::Char('q') => {
-                            self.mode = Mode::Exiting;
+                        KeyCode::Char('n') => {
+                            self.close_buffer();
+                            self.mode = Mode::Normal;
+                        }
+                        KeyCode::Esc => {
+                            self.mode = Mode::Normal;
                         }
                         _ => {}
                     },
                     Mode::Exiting => match key.code {
                         KeyCode::Char('y') => {
-                            self.exit = true;
-                            return Ok(());
+                            if self.save_all_and_report() {
+                                self.exit = true;
+                            } else {
+                                self.mode = Mode::Normal;
+                            }
                         }
                         KeyCode::Char('n') | KeyCode::Char('q') => {
                             self.exit = true;
-                            return Ok(());
+                        }
+                        KeyCode::Esc => {
+                            self.mode = Mode::Normal;
                         }
                         _ => {}
                     },
                     Mode::Editing if key.kind == KeyEventKind::Press => match key.code {
                         KeyCode::Enter => {
-                            self.cursor_up_and_down_column_position_locked = false;
-                            self.jump_to_new_line();
+                            self.buffer_mut().unlock_cursor_column();
+                            let inserted_text = self.buffer_mut().jump_to_new_line();
+                            self.record_insert(|inserted| inserted.push_str(&inserted_text));
+                        }
+                        // terminals send Ctrl+Backspace as Ctrl+H
+                        KeyCode::Backspace | KeyCode::Char('h')
+                            if key.modifiers == KeyModifiers::CONTROL =>
+                        {
+                            self.delete_word(false);
+                        }
+                        KeyCode::Delete if key.modifiers == KeyModifiers::CONTROL => {
+                            self.delete_word(true);
+                        }
+                        KeyCode::Delete => {
+                            self.buffer_mut().unlock_cursor_column();
+                            self.buffer_mut().delete_char_forward();
                         }
                         KeyCode::Backspace => {
-                            self.cursor_up_and_down_column_position_locked = false;
-                            self.delete_char();
+                            self.buffer_mut().unlock_cursor_column();
+                            self.buffer_mut().delete_char();
+                            self.record_insert(|inserted| {
+                                inserted.pop();
+                            });
                         }
                         KeyCode::Esc => {
-                            self.cursor_up_and_down_column_position_locked = false;
+                            self.buffer_mut().unlock_cursor_column();
+                            self.end_insert();
                             self.mode = Mode::Normal;
                         }
-                        KeyCode::Tab => {}
+                        KeyCode::Tab | KeyCode::BackTab => {
+                            self.indent(key.code == KeyCode::BackTab);
+                        }
+                        KeyCode::Char('r') if key.modifiers == KeyModifiers::CONTROL => {
+                            self.pending_register_insert = true;
+                        }
                         KeyCode::Char('z') => {
                             if key.modifiers == KeyModifiers::CONTROL {
-                                self.undo();
+                                self.buffer_mut().undo();
                             } else {
-                                self.cursor_up_and_down_column_position_locked = false;
-                                self.add_char('z');
+                                self.buffer_mut().unlock_cursor_column();
+                                self.buffer_mut().add_char('z');
+                                self.record_insert(|inserted| inserted.push('z'));
                             }
                         }
                         KeyCode::Char('y') => {
                             if key.modifiers == KeyModifiers::CONTROL {
-                                self.redo();
+                                self.buffer_mut().redo();
                             } else {
-                                self.cursor_up_and_down_column_position_locked = false;
-                                self.add_char('y');
+                                self.buffer_mut().unlock_cursor_column();
+                                self.buffer_mut().add_char('y');
+                                self.record_insert(|inserted| inserted.push('y'));
                             }
                         }
                         KeyCode::Char(value) => {
-                            self.cursor_up_and_down_column_position_locked = false;
-                            self.add_char(value);
+                            self.buffer_mut().unlock_cursor_column();
+                            self.buffer_mut().add_char(value);
+                            self.record_insert(|inserted| inserted.push(value));
+                        }
+                        KeyCode::Left | KeyCode::Right
+                            if key.modifiers == KeyModifiers::CONTROL =>
+                        {
+                            self.move_by_word(key.code == KeyCode::Right);
                         }
                         KeyCode::Left => {
-                            self.cursor_up_and_down_column_position_locked = false;
-                            self.move_cursor_left(0, self.index.saturating_sub(1));
+                            self.buffer_mut().unlock_cursor_column();
+                            self.buffer_mut().move_left();
                         }
                         KeyCode::Right => {
-                            self.cursor_up_and_down_column_position_locked = false;
-                            self.move_cursor_right(self.index + 1)
+                            self.buffer_mut().unlock_cursor_column();
+                            self.buffer_mut().move_right();
                         }
                         KeyCode::Up => {
-                            self.move_line_up();
+                            self.buffer_mut().move_line_up();
                         }
                         KeyCode::Down => {
-                            self.move_line_down();
+                            self.buffer_mut().move_line_down();
                         }
                         _ => {}
                     },
                     _ => {}
                 }
             }
+            Event::Resize(width, height) => self.resize(width as usize, height as usize),
             Event::Paste(pasted_string) => {
-                if let Mode::Editing = self.mode {
-                    self.paste(pasted_string);
+                if let Mode::Search = self.mode {
+                    self.search_query.push_str(&pasted_string);
+                    self.update_incremental_search();
+                } else if let Mode::Command = self.mode {
+                    self.command_line.push_str(&pasted_string);
+                } else if let Mode::Replace = self.mode {
+                    match self.replace.stage {
+                        ReplaceStage::Pattern => self.replace.pattern.push_str(&pasted_string),
+                        ReplaceStage::Replacement => {
+                            self.replace.replacement.push_str(&pasted_string)
+                        }
+                        ReplaceStage::Confirm => {}
+                    }
+                } else if let Mode::Editing = self.mode {
+                    self.buffer_mut().paste(pasted_string);
                 }
             }
             _ => (),
         }
-        Ok(())
     }
 }
 
+// the columns left for text beside the gutter, and the rows left between the title and the
+// footer, which are three rows each
+fn window_size(terminal_width: usize, terminal_height: usize) -> (usize, usize) {
+    (
+        terminal_width.saturating_sub(10).max(1),
+        terminal_height.saturating_sub(6).max(1),
+    )
+}
+
 fn centered_rect(percent_x: u16, percent_y: u16, r: Rect) -> Rect {
     // Cut the given rectangle into three vertical pieces
     let popup_layout = Layout::default()
@@ -622,3 +2102,149 @@ pub fn get_line_widths(content: &str) -> (Vec<usize>, usize, usize) {
     }
     (lines_widths, lines_counts / 2, (lines_counts * 2) - 1)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use ratatui::{Terminal, backend::TestBackend, crossterm::event::KeyEvent};
+
+    use super::*;
+    use crate::rope::Rope;
+
+    // keeps what was copied where the test can still see it
+    struct RecordingClipboard(Rc<RefCell<Vec<String>>>);
+    impl Clipboard for RecordingClipboard {
+        fn copy(&mut self, text: &str) -> io::Result<()> {
+            self.0.borrow_mut().push(text.to_string());
+            Ok(())
+        }
+    }
+
+    fn app(content: &str) -> App<Rope> {
+        let mut app = App::new(120, 16);
+        app.open_buffer(
+            "test.txt".to_string(),
+            content.to_string(),
+            Rope::new(content.to_string()),
+        );
+        app
+    }
+    // types the keys out, with a newline standing for Enter and an escape for Esc
+    fn press(app: &mut App<Rope>, keys: &str) {
+        for key in keys.chars() {
+            let code = match key {
+                '\n' => KeyCode::Enter,
+                '\x1b' => KeyCode::Esc,
+                key => KeyCode::Char(key),
+            };
+            app.handle_event(Event::Key(KeyEvent::new(code, KeyModifiers::NONE)));
+        }
+    }
+    fn text(app: &App<Rope>) -> String {
+        let mut text = String::new();
+        app.buffer().text_representation().collect_string(&mut text);
+        text
+    }
+    // the rows of the terminal the app was drawn on, as text
+    fn screen(app: &mut App<Rope>, width: u16, height: u16) -> Vec<String> {
+        let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+        app.prepare_frame();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        (0..buffer.area.height)
+            .map(|y| {
+                (0..buffer.area.width)
+                    .map(|x| buffer[(x, y)].symbol())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn putting_from_a_named_register_draws_the_line_again() {
+        let mut app = app("one\ntwo\n");
+        press(&mut app, "\"ayyj\"ap");
+        let screen = screen(&mut app, 120, 16);
+        assert!(screen[1].contains("test.txt [modified]"));
+        assert_eq!(screen[3].trim_end(), "   1│one");
+        assert_eq!(screen[4].trim_end(), "   2│two");
+        assert_eq!(screen[5].trim_end(), "   3│one");
+    }
+    #[test]
+    fn putting_lines_below_the_last_one_leaves_the_cursor_on_them() {
+        for content in ["one\ntwo\n", "one\ntwo"] {
+            let mut app = app(content);
+            press(&mut app, "yyjp");
+            assert_eq!(app.buffer().logical_line(), 2);
+            assert_eq!(app.buffer().logical_column(), 0);
+        }
+    }
+    #[test]
+    fn putting_from_an_empty_register_says_so_in_the_footer() {
+        let mut app = app("one\n");
+        press(&mut app, "\"bp");
+        let screen = screen(&mut app, 120, 16);
+        assert_eq!(screen[3].trim_end(), "   1│one");
+        assert!(screen[14].contains("register b is empty"));
+    }
+    #[test]
+    fn only_unnamed_and_clipboard_yanks_reach_the_clipboard() {
+        let mut app = app("one\ntwo\n");
+        let copied = Rc::new(RefCell::new(vec![]));
+        app.set_clipboard(Box::new(RecordingClipboard(copied.clone())));
+        press(&mut app, "yy\"ayyj\"+yy\"*yydd");
+        assert_eq!(*copied.borrow(), ["one\n", "two\n", "two\n"]);
+    }
+    #[test]
+    fn replacing_starts_at_the_cursor_and_wraps_around_to_it() {
+        let mut app = app("a1 a2 a3 a4");
+        app.buffer_mut().jump_to_index(3);
+        press(&mut app, "Ra\nbb\n");
+        assert_eq!(app.buffer().index(), 3);
+        press(&mut app, "yny");
+        assert_eq!(text(&app), "a1 bb2 a3 bb4");
+        assert_eq!(app.buffer().index(), 0);
+        press(&mut app, "y");
+        assert_eq!(text(&app), "bb1 bb2 a3 bb4");
+        assert!(matches!(app.mode, Mode::Normal));
+    }
+    #[test]
+    fn replacing_the_rest_takes_in_the_matches_above_the_cursor() {
+        let mut app = app("a1 a2 a3");
+        app.buffer_mut().jump_to_index(3);
+        press(&mut app, "Ra\nb\na");
+        assert_eq!(text(&app), "b1 b2 b3");
+    }
+    #[test]
+    fn normal_mode_keeps_the_cursor_on_the_last_grapheme_of_a_line() {
+        let mut app = app("abc\nde");
+        press(&mut app, "lllll");
+        assert_eq!(app.buffer().index(), 2);
+        press(&mut app, "0$");
+        assert_eq!(app.buffer().index(), 2);
+    }
+    #[test]
+    fn leaving_editing_mode_steps_back_onto_the_last_grapheme_typed() {
+        let mut app = app("ab\ncd");
+        press(&mut app, "aXY\x1b");
+        assert_eq!(text(&app), "aXYb\ncd");
+        assert_eq!(app.buffer().index(), 2);
+        press(&mut app, "j0i\x1b");
+        assert_eq!(app.buffer().logical_column(), 0);
+    }
+    #[test]
+    fn messages_keep_room_beside_a_long_status_line() {
+        let content = "one\n";
+        let mut app = App::new(80, 12);
+        app.open_buffer(
+            "src/some_module/file.rs".to_string(),
+            content.to_string(),
+            Rope::new(content.to_string()),
+        );
+        press(&mut app, "\"bp");
+        let screen = screen(&mut app, 80, 12);
+        assert!(screen[10].contains("register b is empty"));
+        assert!(screen[10].contains("Normal Mode"));
+    }
+}
@@ -0,0 +1,114 @@
+use std::ops::Range;
+
+use crate::{grapheme_reader::GraphemeReader, text_representation::TextRepresentation};
+
+// what typing the opening half of brings in the closing half along with it
+pub const AUTO_PAIRS: &[(char, char)] =
+    &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')];
+const PAIRS: [(&str, &str); 3] = [("(", ")"), ("[", "]"), ("{", "}")];
+// how many graphemes a jump to the matching bracket walks each way, so that an unbalanced bracket
+// in a large file cannot hold up a key press
+pub const SEARCH_LIMIT: usize = 50_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Bracket {
+    Opening(usize),
+    Closing(usize),
+}
+fn bracket(grapheme: &str) -> Option<Bracket> {
+    PAIRS
+        .iter()
+        .enumerate()
+        .find_map(|(kind, (opening, closing))| {
+            if grapheme == *opening {
+                Some(Bracket::Opening(kind))
+            } else if grapheme == *closing {
+                Some(Bracket::Closing(kind))
+            } else {
+                None
+            }
+        })
+}
+
+// whether an index falls in one of the ranges, which are sorted and do not overlap
+fn is_skipped(skipped: &[Range<usize>], index: usize) -> bool {
+    let position = skipped.partition_point(|range| range.end <= index);
+    skipped
+        .get(position)
+        .is_some_and(|range| range.contains(&index))
+}
+
+// walks away from an index for the bracket of a kind that is not balanced out on the way
+fn find_unbalanced(
+    reader: &mut GraphemeReader,
+    index: usize,
+    kind: usize,
+    forward: bool,
+    skipped: &[Range<usize>],
+    limit: usize,
+) -> Option<usize> {
+    let mut depth = 0;
+    let mut position = index;
+    for _ in 0..limit {
+        position = match forward {
+            true if position + 1 < reader.len() => position + 1,
+            false if position > 0 => position - 1,
+            _ => return None,
+        };
+        if is_skipped(skipped, position) {
+            continue;
+        }
+        let found = match (reader.get(position).and_then(bracket), forward) {
+            (Some(Bracket::Closing(found)), true) | (Some(Bracket::Opening(found)), false) => found,
+            (Some(Bracket::Opening(found)), true) | (Some(Bracket::Closing(found)), false)
+                if found == kind =>
+            {
+                depth += 1;
+                continue;
+            }
+            _ => continue,
+        };
+        if found != kind {
+            continue;
+        }
+        if depth == 0 {
+            return Some(position);
+        }
+        depth -= 1;
+    }
+    None
+}
+
+// the bracket at the index and its partner, or else the closest pair the index sits inside,
+// leaving out brackets in the skipped ranges unless the index is in one of them itself, and
+// looking no further than the limit each way
+pub fn bracket_pair(
+    text_representation: &dyn TextRepresentation,
+    index: usize,
+    skipped: &[Range<usize>],
+    limit: usize,
+) -> Option<(usize, usize)> {
+    let skipped = match is_skipped(skipped, index) {
+        true => &[],
+        false => skipped,
+    };
+    let mut reader = GraphemeReader::new(text_representation);
+    match reader.get(index).and_then(bracket) {
+        Some(Bracket::Opening(kind)) => {
+            find_unbalanced(&mut reader, index, kind, true, skipped, limit).map(|end| (index, end))
+        }
+        Some(Bracket::Closing(kind)) => {
+            find_unbalanced(&mut reader, index, kind, false, skipped, limit)
+                .map(|start| (start, index))
+        }
+        None => PAIRS
+            .iter()
+            .enumerate()
+            .filter_map(|(kind, _)| {
+                let start = find_unbalanced(&mut reader, index, kind, false, skipped, limit)?;
+                let end = find_unbalanced(&mut reader, index, kind, true, skipped, limit)?;
+                Some((start, end))
+            })
+            .max_by_key(|(start, _)| *start),
+    }
+}
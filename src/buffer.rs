@@ -0,0 +1,1243 @@
+use std::{cmp::min, io, ops::Range};
+
+use ratatui::style::Style;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    brackets::{AUTO_PAIRS, SEARCH_LIMIT, bracket_pair},
+    command::{
+        InsertIntoLineCommand, LineCommandContext, RemoveFromLineCommand, ReplaceLinesCommand,
+        TextEditorLineCommand,
+    },
+    gap_buffer::LinesGapBuffer,
+    highlight::{Highlighter, LineSplice, grammar_for_path},
+    indent::{Indentation, closer_of, leading_whitespace},
+    search::RegexMatch,
+    text_editor_line::WrapOptions,
+    text_representation::TextRepresentation,
+    utils::{encoding_of, line_ending_of, write_file_atomically},
+};
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Viewport {
+    pub row_number: usize,
+    pub column_number: usize,
+    pub index: usize,
+    pub page_start: usize,
+    pub page_index: usize,
+    // the display columns cut off the left of the window when lines are not wrapped
+    pub scroll_column: usize,
+}
+pub struct Buffer<T: TextRepresentation> {
+    file_path: String,
+    text_representation: T,
+    lines_text_editor: LinesGapBuffer,
+    highlighter: Option<Highlighter>,
+    row_number: usize,
+    column_number: usize,
+    index: usize,
+    cursor_up_and_down_column_position_locked: bool,
+    global_up_and_down_column_position: usize,
+    undo_line_commands: Vec<Box<dyn TextEditorLineCommand>>,
+    redo_line_commands: Vec<Box<dyn TextEditorLineCommand>>,
+    window_height: usize,
+    window_width: usize,
+    page_index: usize,
+    page_start: usize,
+    scroll_column: usize,
+    indentation: Indentation,
+    wrapping: bool,
+    wrap_at_words: bool,
+    hanging_indent: bool,
+    line_ending: &'static str,
+    encoding: &'static str,
+    // closers that auto-pairing put in and that have not been edited since, which are the only
+    // ones typing the same closer steps over
+    pending_closers: Vec<(usize, char)>,
+}
+
+impl<T: TextRepresentation> Buffer<T> {
+    pub fn new(
+        file_path: String,
+        starting_string: String,
+        text_representation: T,
+        window_width: usize,
+        window_height: usize,
+    ) -> Self {
+        let indentation = Indentation::detect(&starting_string);
+        let line_ending = line_ending_of(&starting_string);
+        let encoding = encoding_of(&starting_string);
+        let lines_text_editor = LinesGapBuffer::new(
+            &starting_string,
+            WrapOptions {
+                width: window_width,
+                tab_width: indentation.width,
+                enabled: true,
+                at_words: true,
+                hanging_indent: false,
+            },
+        );
+        let highlighter = grammar_for_path(&file_path).map(Highlighter::new);
+        Self {
+            file_path,
+            text_representation,
+            lines_text_editor,
+            highlighter,
+            row_number: 0,
+            column_number: 0,
+            index: 0,
+            cursor_up_and_down_column_position_locked: false,
+            global_up_and_down_column_position: 0,
+            undo_line_commands: vec![],
+            redo_line_commands: vec![],
+            window_height,
+            window_width,
+            page_start: 0,
+            page_index: 0,
+            scroll_column: 0,
+            indentation,
+            wrapping: true,
+            wrap_at_words: true,
+            hanging_indent: false,
+            line_ending,
+            encoding,
+            pending_closers: vec![],
+        }
+    }
+    pub fn text_representation(&self) -> &T {
+        &self.text_representation
+    }
+    pub fn lines_text_editor(&self) -> &LinesGapBuffer {
+        &self.lines_text_editor
+    }
+    pub fn highlighter(&self) -> Option<&Highlighter> {
+        self.highlighter.as_ref()
+    }
+    pub fn refresh_highlighting(&mut self, up_to_row: usize) {
+        if let Some(highlighter) = &mut self.highlighter {
+            highlighter.refresh(&self.lines_text_editor, up_to_row);
+        }
+    }
+    // the bracket under the cursor and its partner, or the closest pair around the cursor, with
+    // brackets in strings and comments left out once the highlighter has reached them
+    pub fn bracket_pair(&self) -> Option<(usize, usize)> {
+        self.bracket_pair_within(SEARCH_LIMIT)
+    }
+    // the pair highlighted as the cursor moves, which is looked for on every frame and so no
+    // further than about a screen of text away
+    pub fn visible_bracket_pair(&self) -> Option<(usize, usize)> {
+        self.bracket_pair_within(self.window_width * self.window_height)
+    }
+    fn bracket_pair_within(&self, limit: usize) -> Option<(usize, usize)> {
+        // a search never goes further than this, so strings and comments past it do not matter
+        let window = self.index.saturating_sub(limit)..self.index + limit + 1;
+        let skipped = match &self.highlighter {
+            Some(highlighter) => {
+                highlighter.string_and_comment_ranges(&self.lines_text_editor, window)
+            }
+            None => vec![],
+        };
+        bracket_pair(&self.text_representation, self.index, &skipped, limit)
+    }
+    // jumps to the other end of the bracket pair, or to the opening bracket from inside one
+    pub fn jump_to_matching_bracket(&mut self) {
+        self.refresh_highlighting(self.lines_text_editor.length());
+        let Some((start, end)) = self.bracket_pair() else {
+            return;
+        };
+        let target = match self.index == start {
+            true => end,
+            false => start,
+        };
+        self.goto_offset(target);
+        self.unlock_cursor_column();
+    }
+    pub fn syntax_highlights(
+        &self,
+        viewport: Viewport,
+        height: usize,
+    ) -> Vec<(Range<usize>, Style)> {
+        match &self.highlighter {
+            Some(highlighter) => highlighter.highlights_for_page(
+                &self.lines_text_editor,
+                viewport.page_start,
+                height,
+                viewport.page_index,
+            ),
+            None => vec![],
+        }
+    }
+    fn splice_highlighting(&mut self, splice: LineSplice) {
+        if let Some(highlighter) = &mut self.highlighter {
+            highlighter.splice(splice);
+        }
+    }
+    pub fn row_number(&self) -> usize {
+        self.row_number
+    }
+    pub fn column_number(&self) -> usize {
+        self.column_number
+    }
+    pub fn index(&self) -> usize {
+        self.index
+    }
+    pub fn page_start(&self) -> usize {
+        self.page_start
+    }
+    pub fn window_width(&self) -> usize {
+        self.window_width
+    }
+    pub fn set_window_height(&mut self, window_height: usize) {
+        self.window_height = window_height;
+    }
+    pub fn viewport(&self) -> Viewport {
+        Viewport {
+            row_number: self.row_number,
+            column_number: self.column_number,
+            index: self.index,
+            page_start: self.page_start,
+            page_index: self.page_index,
+            scroll_column: self.scroll_column,
+        }
+    }
+    pub fn set_viewport(&mut self, viewport: Viewport) {
+        self.row_number = viewport.row_number;
+        self.column_number = viewport.column_number;
+        self.index = viewport.index;
+        self.page_start = viewport.page_start;
+        self.page_index = viewport.page_index;
+        self.scroll_column = viewport.scroll_column;
+        self.cursor_up_and_down_column_position_locked = false;
+    }
+    pub fn viewport_for_index(&self, index: usize, page_start: usize, height: usize) -> Viewport {
+        let (row, column_number) = self.lines_text_editor.find_where_rope_index_fits(index);
+        let height = height.max(1);
+        let mut page_start = min(
+            page_start,
+            self.lines_text_editor.length().saturating_sub(1),
+        );
+        if row < page_start {
+            page_start = row;
+        } else if row >= page_start + height {
+            page_start = row + 1 - height;
+        }
+        let page_index = self
+            .lines_text_editor
+            .length_up_to_non_inclusive_index_for_current_page(page_start, 0, 0);
+        Viewport {
+            row_number: row - page_start,
+            column_number,
+            index: page_index
+                + self
+                    .lines_text_editor
+                    .length_up_to_non_inclusive_index_for_current_page(row, page_start, 0)
+                + column_number,
+            page_start,
+            page_index,
+            scroll_column: self.scroll_column,
+        }
+    }
+    pub fn refresh_viewport(&self, viewport: Viewport, height: usize) -> Viewport {
+        Viewport {
+            scroll_column: viewport.scroll_column,
+            ..self.viewport_for_index(viewport.index, viewport.page_start, height)
+        }
+    }
+    // keeps the cursor in view when lines run off the side of the window instead of wrapping
+    pub fn scroll_to_cursor(&mut self) {
+        if self.wrapping {
+            self.scroll_column = 0;
+            return;
+        }
+        let column = self
+            .lines_text_editor
+            .display_column(self.page_start + self.row_number, self.column_number);
+        if column < self.scroll_column {
+            self.scroll_column = column;
+        } else if column >= self.scroll_column + self.window_width {
+            self.scroll_column = column + 1 - self.window_width;
+        }
+    }
+    pub fn jump_to_index(&mut self, index: usize) {
+        let viewport = self.viewport_for_index(index, self.page_start, self.window_height);
+        self.set_viewport(viewport);
+    }
+    pub fn line_ending(&self) -> &'static str {
+        self.line_ending
+    }
+    // what a line break is written as in the buffer, going by its line ending; the lines only
+    // break at newlines, so a file of lone carriage returns is edited with newlines and gets its
+    // carriage returns back when it is written
+    pub fn line_break(&self) -> &'static str {
+        match self.line_ending {
+            "crlf" => "\r\n",
+            _ => "\n",
+        }
+    }
+    pub fn encoding(&self) -> &'static str {
+        self.encoding
+    }
+    pub fn language(&self) -> &'static str {
+        self.highlighter
+            .as_ref()
+            .map_or("plain text", Highlighter::language)
+    }
+    pub fn logical_line(&self) -> usize {
+        self.lines_text_editor
+            .logical_line_of(self.page_start + self.row_number)
+    }
+    pub fn logical_line_at(&self, index: usize) -> usize {
+        let (row, _) = self.lines_text_editor.find_where_rope_index_fits(index);
+        self.lines_text_editor.logical_line_of(row)
+    }
+    pub fn logical_column(&self) -> usize {
+        self.index
+            .saturating_sub(self.logical_line_start(self.logical_line()))
+    }
+    fn logical_line_start(&self, line: usize) -> usize {
+        let first_row = self
+            .lines_text_editor
+            .first_row_of_logical_line(line)
+            .unwrap_or_default();
+        self.lines_text_editor
+            .length_up_to_non_inclusive_index_for_current_page(first_row, 0, 0)
+    }
+    // jumps to a column of a logical line, both counted from zero and clamped to the text
+    pub fn goto_line(&mut self, line: usize, column: usize) {
+        let last_line = self
+            .lines_text_editor
+            .logical_line_count()
+            .saturating_sub(1);
+        let Some(first_row) = self
+            .lines_text_editor
+            .first_row_of_logical_line(line.min(last_line))
+        else {
+            return;
+        };
+        let line_start = self.logical_line_start(line.min(last_line));
+        let column = column.min(self.lines_text_editor.logical_line_length(first_row));
+        self.goto_offset(line_start + column);
+    }
+    // like jump_to_index, but a target off the page lands in the middle of the window
+    pub fn goto_offset(&mut self, index: usize) {
+        let index = index.min(self.text_representation.length());
+        let (row, _) = self.lines_text_editor.find_where_rope_index_fits(index);
+        let height = self.window_height.max(1);
+        let last_row = self.lines_text_editor.length().saturating_sub(1);
+        let page_start = if row < self.page_start || row >= self.page_start + height {
+            row.saturating_sub(height / 2)
+                .min(last_row.saturating_sub(height - 1))
+        } else {
+            self.page_start
+        };
+        let viewport = self.viewport_for_index(index, page_start, height);
+        self.set_viewport(viewport);
+    }
+    pub fn page_down(&mut self) {
+        self.move_by_page(true);
+    }
+    pub fn page_up(&mut self) {
+        self.move_by_page(false);
+    }
+    // moves the page and the cursor a window height at once, keeping the cursor on its screen row
+    fn move_by_page(&mut self, down: bool) {
+        if !self.cursor_up_and_down_column_position_locked {
+            self.global_up_and_down_column_position = self.column_number;
+            self.cursor_up_and_down_column_position_locked = true;
+        }
+        let height = self.window_height.max(1);
+        let last_row = self.lines_text_editor.length().saturating_sub(1);
+        let row = self.page_start + self.row_number;
+        let (target_row, page_start) = match down {
+            // the last page still fills the window
+            true => (
+                (row + height).min(last_row),
+                (self.page_start + height)
+                    .min(last_row.saturating_sub(height - 1))
+                    .max(self.page_start),
+            ),
+            false => (
+                row.saturating_sub(height),
+                self.page_start.saturating_sub(height),
+            ),
+        };
+        let column = self
+            .lines_text_editor
+            .index(target_row)
+            .unwrap_or_default()
+            .min(self.global_up_and_down_column_position);
+        let index = self
+            .lines_text_editor
+            .length_up_to_non_inclusive_index_for_current_page(target_row, 0, 0)
+            + column;
+        let viewport = self.viewport_for_index(index, page_start, height);
+        self.set_viewport(viewport);
+        self.cursor_up_and_down_column_position_locked = true;
+    }
+    pub fn move_to_line_start(&mut self) {
+        self.goto_line(self.logical_line(), 0);
+    }
+    pub fn move_to_line_end(&mut self) {
+        self.goto_line(self.logical_line(), usize::MAX);
+    }
+    pub fn move_to_start(&mut self) {
+        self.goto_offset(0);
+    }
+    pub fn move_to_end(&mut self) {
+        self.goto_offset(self.text_representation.length());
+    }
+    pub fn reflow(&mut self, window_width: usize) {
+        self.window_width = window_width;
+        self.rebuild_lines();
+        self.undo_line_commands.clear();
+        self.redo_line_commands.clear();
+        self.jump_to_index(self.index);
+    }
+    fn rebuild_lines(&mut self) {
+        let mut contents = String::new();
+        self.text_representation.collect_string(&mut contents);
+        self.lines_text_editor = LinesGapBuffer::new(
+            &contents,
+            WrapOptions {
+                width: self.window_width,
+                tab_width: self.indentation.width,
+                enabled: self.wrapping,
+                at_words: self.wrap_at_words,
+                hanging_indent: self.hanging_indent,
+            },
+        );
+    }
+    // whether long lines wrap at all, whether they break at word boundaries, and whether their
+    // continuation rows hang under the indentation of the line
+    pub fn set_wrap_style(&mut self, wrapping: bool, at_words: bool, hanging_indent: bool) {
+        if (wrapping, at_words, hanging_indent)
+            == (self.wrapping, self.wrap_at_words, self.hanging_indent)
+        {
+            return;
+        }
+        self.wrapping = wrapping;
+        self.wrap_at_words = at_words;
+        self.hanging_indent = hanging_indent;
+        self.reflow(self.window_width);
+    }
+    pub fn indentation(&self) -> Indentation {
+        self.indentation
+    }
+    pub fn set_indentation(&mut self, indentation: Indentation) {
+        let tab_width_changed = indentation.width != self.indentation.width;
+        self.indentation = indentation;
+        // hard tabs are drawn at the new width, so the rows wrap differently
+        if tab_width_changed {
+            self.reflow(self.window_width);
+        }
+    }
+    // indents or outdents each logical line in the range by one level, as one undo step
+    pub fn indent_lines(&mut self, lines: Range<usize>, outdent: bool) {
+        let line_count = self.lines_text_editor.logical_line_count();
+        let lines = lines.start.min(line_count.saturating_sub(1))..lines.end.min(line_count);
+        if lines.is_empty() {
+            return;
+        }
+        let unit = self.indentation.unit();
+        let mut replacements = Vec::new();
+        let mut index = self.index;
+        for line in lines.clone() {
+            let line_start = self.logical_line_start(line);
+            let leading = self.text_in(
+                line_start
+                    ..(line_start + self.indentation.width).min(self.text_representation.length()),
+            );
+            let is_empty = leading.is_empty() || leading.starts_with('\n');
+            let replacement = if outdent {
+                let length = self.indentation.outdent_length(&leading);
+                if line_start <= self.index {
+                    index -= length.min(self.index - line_start);
+                }
+                RegexMatch {
+                    start: line_start,
+                    length,
+                    replacement: String::new(),
+                }
+            } else if is_empty && lines.len() > 1 {
+                // blank lines among several are left without trailing whitespace
+                continue;
+            } else {
+                if line_start <= self.index {
+                    index += unit.graphemes(true).count();
+                }
+                RegexMatch {
+                    start: line_start,
+                    length: 0,
+                    replacement: unit.clone(),
+                }
+            };
+            if replacement.length > 0 || !replacement.replacement.is_empty() {
+                replacements.push(replacement);
+            }
+        }
+        if replacements.is_empty() {
+            return;
+        }
+        let first_line_start = self.logical_line_start(lines.start);
+        self.pending_closers.clear();
+        self.text_representation.replace(replacements);
+        self.execute_line_command(ReplaceLinesCommand::new(
+            lines.start,
+            first_line_start,
+            lines.len(),
+            lines.len(),
+        ));
+        self.jump_to_index(index);
+    }
+    pub fn unlock_cursor_column(&mut self) {
+        self.cursor_up_and_down_column_position_locked = false;
+    }
+    pub fn move_left(&mut self) {
+        self.move_cursor_left(0, self.index.saturating_sub(1));
+    }
+    pub fn move_right(&mut self) {
+        self.move_cursor_right(self.index + 1)
+    }
+    pub fn file_path(&self) -> &str {
+        &self.file_path
+    }
+    pub fn is_modified(&self) -> bool {
+        self.text_representation.is_modified()
+    }
+    pub fn save(&mut self) -> io::Result<()> {
+        self.write_to(&self.file_path)?;
+        self.text_representation.mark_saved();
+        Ok(())
+    }
+    // writes a copy elsewhere, leaving the buffer tied to its own file
+    pub fn write_to(&self, file_path: &str) -> io::Result<()> {
+        let mut contents = String::new();
+        self.text_representation.collect_string(&mut contents);
+        if self.line_ending == "cr" {
+            contents = contents.replace("\r\n", "\n").replace('\n', "\r");
+        }
+        write_file_atomically(file_path, &contents)
+    }
+    fn execute_line_command<C: TextEditorLineCommand + 'static>(&mut self, command: C) {
+        self.splice_highlighting(command.lines_touched(&self.lines_text_editor));
+        command.execute(LineCommandContext::new(
+            &mut self.lines_text_editor,
+            &self.text_representation,
+        ));
+        self.redo_line_commands.clear();
+        self.undo_line_commands.push(Box::new(command));
+    }
+    pub fn redo(&mut self) {
+        if let Some(new_index) = self.text_representation.redo() {
+            self.pending_closers.clear();
+            match self.redo_line_commands.pop() {
+                Some(last_line_command) => {
+                    self.splice_highlighting(
+                        last_line_command.lines_touched(&self.lines_text_editor),
+                    );
+                    last_line_command.execute(LineCommandContext::new(
+                        &mut self.lines_text_editor,
+                        &self.text_representation,
+                    ));
+                    self.undo_line_commands.push(last_line_command);
+                }
+                None => {
+                    self.rebuild_lines();
+                    self.splice_highlighting(LineSplice::All);
+                }
+            }
+            self.jump_to_index(new_index);
+        }
+    }
+    pub fn undo(&mut self) {
+        if let Some(new_index) = self.text_representation.undo() {
+            self.pending_closers.clear();
+            match self.undo_line_commands.pop() {
+                Some(last_line_command) => {
+                    self.splice_highlighting(
+                        last_line_command.lines_touched_by_undo(&self.lines_text_editor),
+                    );
+                    last_line_command.undo(LineCommandContext::new(
+                        &mut self.lines_text_editor,
+                        &self.text_representation,
+                    ));
+                    self.redo_line_commands.push(last_line_command);
+                }
+                // the line history was dropped by a reflow, so rebuild from the rope
+                None => {
+                    self.rebuild_lines();
+                    self.splice_highlighting(LineSplice::All);
+                }
+            }
+            self.jump_to_index(new_index);
+        }
+    }
+
+    pub fn move_line_down(&mut self) {
+        if !self.cursor_up_and_down_column_position_locked {
+            self.global_up_and_down_column_position = self.column_number;
+            self.cursor_up_and_down_column_position_locked = true;
+        }
+        match self
+            .lines_text_editor
+            .index(self.page_start + self.row_number + 1)
+        {
+            Some(next_line_length) => {
+                self.column_number = min(next_line_length, self.global_up_and_down_column_position);
+                if self.row_number + 1 >= self.window_height {
+                    self.scroll_one_line_down();
+                } else {
+                    self.row_number += 1;
+                }
+            }
+            None => {
+                return;
+            }
+        }
+        let length_upto_non_inclusive_current_row = self
+            .lines_text_editor
+            .length_up_to_non_inclusive_index_for_current_page(
+                self.page_start + self.row_number,
+                self.page_start,
+                self.page_index,
+            );
+        self.index = length_upto_non_inclusive_current_row + self.column_number;
+    }
+    fn scroll_one_line_down(&mut self) {
+        self.page_index += self
+            .lines_text_editor
+            .index_for_offset(self.page_start)
+            .unwrap_or_default();
+        self.page_start += 1;
+    }
+    fn scroll_one_line_up(&mut self) {
+        self.page_index = match self.lines_text_editor.index_for_offset(self.page_start - 1) {
+            Some(line_length) => self.page_index - line_length,
+            None => self.page_index,
+        };
+        self.page_start -= 1;
+    }
+    pub fn move_line_up(&mut self) {
+        if !self.cursor_up_and_down_column_position_locked {
+            self.global_up_and_down_column_position = self.column_number;
+            self.cursor_up_and_down_column_position_locked = true;
+        }
+        let previous_global_row_number = if self.row_number == 0 && self.page_start == 0 {
+            return;
+        } else {
+            self.row_number + self.page_start - 1
+        };
+        match self.lines_text_editor.index(previous_global_row_number) {
+            Some(previous_line_length) => {
+                self.column_number = min(
+                    previous_line_length,
+                    self.global_up_and_down_column_position,
+                );
+            }
+            None => {
+                self.column_number = 0;
+            }
+        }
+        if self.row_number == 0 {
+            self.scroll_one_line_up();
+        } else {
+            self.row_number -= 1;
+        }
+        let length_upto_non_inclusive_current_row = self
+            .lines_text_editor
+            .length_up_to_non_inclusive_index_for_current_page(
+                self.page_start + self.row_number,
+                self.page_start,
+                self.page_index,
+            );
+        self.index = length_upto_non_inclusive_current_row + self.column_number;
+    }
+    pub fn text_in(&self, range: Range<usize>) -> String {
+        let range = range.start..range.end.min(self.text_representation.length());
+        let mut text = String::new();
+        if !range.is_empty() {
+            self.text_representation
+                .collect_substring(&mut text, (range.start, range.end - 1));
+        }
+        text
+    }
+    // removes a range that may span several lines, handing back what was cut
+    pub fn delete_range(&mut self, range: Range<usize>) -> String {
+        let range = range.start..range.end.min(self.text_representation.length());
+        if range.is_empty() {
+            return String::new();
+        }
+        let (first_row, _) = self
+            .lines_text_editor
+            .find_where_rope_index_fits(range.start);
+        let (last_row, _) = self.lines_text_editor.find_where_rope_index_fits(range.end);
+        let line = self.lines_text_editor.logical_line_of(first_row);
+        let removed = self.lines_text_editor.logical_line_of(last_row) + 1 - line;
+        let line_start = self.logical_line_start(line);
+        self.text_representation.delete(range.len(), range.start);
+        self.shift_pending_closers(range.start, range.len(), 0);
+        let cut_content = self.text_representation.last_cut().unwrap_or_default();
+        // whatever is left of the lines the range went through joins up into one
+        self.execute_line_command(ReplaceLinesCommand::new(line, line_start, removed, 1));
+        self.jump_to_index(range.start);
+        cut_content
+    }
+    // the Delete key, which joins the next line on when the cursor is at the end of one
+    pub fn delete_char_forward(&mut self) {
+        let index = self.index;
+        if index < self.text_representation.length() {
+            self.delete_range(index..index + 1);
+        }
+    }
+    // inserts text that may hold newlines, splitting the line it goes into as one undo step
+    pub fn insert_text(&mut self, index: usize, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let (row, _) = self.lines_text_editor.find_where_rope_index_fits(index);
+        // the empty line after a final newline may have no row yet, in which case the text goes
+        // into a line of its own instead of replacing one
+        let (line, line_start, removed) = if self.is_after_final_newline(index) {
+            (self.lines_text_editor.logical_line_of(row) + 1, index, 0)
+        } else {
+            let line = self.lines_text_editor.logical_line_of(row);
+            (line, self.logical_line_start(line), 1)
+        };
+        self.text_representation.insert(text.to_string(), index);
+        self.shift_pending_closers(index, 0, text.graphemes(true).count());
+        let inserted = 1 + text.matches('\n').count();
+        self.execute_line_command(ReplaceLinesCommand::new(
+            line, line_start, removed, inserted,
+        ));
+        self.jump_to_index(index + text.graphemes(true).count());
+    }
+    // whether the index is after a final newline that no row has been made for, as when a
+    // file ending in one has just been loaded
+    fn is_after_final_newline(&self, index: usize) -> bool {
+        if index == 0 || index < self.text_representation.length() {
+            return false;
+        }
+        let mut last = String::new();
+        self.text_representation
+            .collect_substring(&mut last, (index - 1, index - 1));
+        let rows_end = self
+            .lines_text_editor
+            .length_up_to_non_inclusive_index_for_current_page(
+                self.lines_text_editor.length(),
+                0,
+                0,
+            );
+        last.ends_with('\n') && rows_end == index
+    }
+    pub fn replace(&mut self, replacements: Vec<RegexMatch>) {
+        if replacements.is_empty() {
+            return;
+        }
+        // only the logical lines from the first match to the last one are wrapped again
+        let first = replacements
+            .iter()
+            .map(|regex_match| regex_match.start)
+            .min()
+            .unwrap_or_default();
+        let last = replacements
+            .iter()
+            .map(|regex_match| regex_match.start + regex_match.length)
+            .max()
+            .unwrap_or_default();
+        let line = self.logical_line_at(first);
+        let line_start = self.logical_line_start(line);
+        let removed = self.logical_line_at(last) + 1 - line;
+        let removed_breaks: usize = replacements
+            .iter()
+            .filter(|regex_match| regex_match.length > 0)
+            .map(|regex_match| {
+                self.text_in(regex_match.start..regex_match.start + regex_match.length)
+                    .matches('\n')
+                    .count()
+            })
+            .sum();
+        let inserted_breaks: usize = replacements
+            .iter()
+            .map(|regex_match| regex_match.replacement.matches('\n').count())
+            .sum();
+        // matches can land anywhere, so auto-inserted closers are no longer told apart
+        self.pending_closers.clear();
+        let final_index = self.text_representation.replace(replacements);
+        self.execute_line_command(ReplaceLinesCommand::new(
+            line,
+            line_start,
+            removed,
+            removed - removed_breaks + inserted_breaks,
+        ));
+        self.jump_to_index(final_index);
+    }
+    pub fn delete_char(&mut self) {
+        if self.delete_empty_pair() {
+            return;
+        }
+        let index = self.index;
+        let row = self.page_start + self.row_number;
+        if index == 0 {
+            return;
+        }
+        // joining lines or shortening a wrapped one moves graphemes between rows, so the line is
+        // wrapped again as a whole
+        if self.column_number == 0 || !self.lines_text_editor.is_independent(row) {
+            self.delete_range(index - 1..index);
+            return;
+        }
+        let final_index = self.text_representation.delete(1, index - 1);
+        self.shift_pending_closers(index - 1, 1, 0);
+        let (starting, ending, should_offset) = self.lines_text_editor.find_offsets_for_line(row);
+        self.execute_line_command(RemoveFromLineCommand::new(
+            row,
+            1,
+            (starting, ending),
+            should_offset,
+        ));
+        self.move_cursor_left(0, final_index);
+    }
+    pub fn add_char(&mut self, value: char) {
+        if self.type_over_closer(value) || self.insert_pair(value) || self.dedent_for_closer(value)
+        {
+            return;
+        }
+        let row = self.page_start + self.row_number;
+        // a character that makes the row wrap is laid out with the rest of its line instead
+        if !self.lines_text_editor.is_independent(row)
+            || !self
+                .lines_text_editor
+                .fits_with_insert(row, self.column_number, &value.to_string())
+        {
+            self.insert_text(self.index, &value.to_string());
+            return;
+        }
+        let final_index = self
+            .text_representation
+            .insert(value.to_string(), self.index);
+        self.shift_pending_closers(self.index, 0, 1);
+        let (starting, ending, should_offset) = self
+            .lines_text_editor
+            .find_offsets_for_line(self.page_start + self.row_number);
+        self.execute_line_command(InsertIntoLineCommand::new(
+            self.page_start + self.row_number,
+            1,
+            (starting, ending),
+            should_offset,
+        ));
+        self.move_cursor_right(final_index);
+    }
+    pub fn paste(&mut self, value: String) {
+        // terminals hand over pasted line breaks as carriage returns, and they go in the way Enter
+        // breaks lines
+        let value = value
+            .replace("\r\n", "\n")
+            .replace('\r', "\n")
+            .replace('\n', self.line_break());
+        let index = self.index;
+        self.insert_text(index, &value);
+    }
+
+    // Enter carries the indentation of the line over, a level deeper after an opening bracket of
+    // the language, and hands back what went in before the cursor
+    pub fn jump_to_new_line(&mut self) -> String {
+        let index = self.index;
+        let line_start = self.logical_line_start(self.logical_line());
+        let before = self.text_in(line_start..index);
+        let mut text = format!("{}{}", self.line_break(), leading_whitespace(&before));
+        let opener = self.highlighter.as_ref().and_then(|highlighter| {
+            highlighter
+                .indent_openers()
+                .iter()
+                .find(|opener| before.trim_end().ends_with(**opener))
+        });
+        let Some(opener) = opener else {
+            self.insert_text(index, &text);
+            return text;
+        };
+        let indentation_of_line = text.clone();
+        text.push_str(&self.indentation.unit());
+        // a closer right at the cursor moves down to a line of its own
+        let after = self.text_in(index..(index + 1).min(self.text_representation.length()));
+        if closer_of(opener).is_some_and(|closer| after.starts_with(closer)) {
+            self.insert_text(index, &format!("{}{}", text, indentation_of_line));
+            self.jump_to_index(index + text.graphemes(true).count());
+        } else {
+            self.insert_text(index, &text);
+        }
+        text
+    }
+    fn auto_pairs(&self) -> &'static [(char, char)] {
+        self.highlighter
+            .as_ref()
+            .map_or(AUTO_PAIRS, |highlighter| highlighter.auto_pairs())
+    }
+    // typing a closer right in front of one that auto-pairing put in steps over it instead
+    fn type_over_closer(&mut self, value: char) -> bool {
+        let index = self.index;
+        let Some(position) = self
+            .pending_closers
+            .iter()
+            .position(|pending| *pending == (index, value))
+        else {
+            return false;
+        };
+        self.pending_closers.remove(position);
+        self.jump_to_index(index + 1);
+        true
+    }
+    // keeps the pending closers on their characters as text before them comes and goes, and
+    // forgets the ones that were deleted
+    fn shift_pending_closers(&mut self, at: usize, removed: usize, inserted: usize) {
+        self.pending_closers.retain_mut(|(index, _)| {
+            if *index >= at + removed {
+                *index = *index + inserted - removed;
+                true
+            } else {
+                *index < at
+            }
+        });
+    }
+    // an opener brings its closer along with the cursor left in between, in one undo step
+    fn insert_pair(&mut self, value: char) -> bool {
+        let auto_pairs = self.auto_pairs();
+        let Some((_, closer)) = auto_pairs.iter().find(|(opener, _)| *opener == value) else {
+            return false;
+        };
+        let index = self.index;
+        // pairing only where the pair cannot swallow the text that follows
+        let next = self.text_in(index..index + 1);
+        let is_open_after = next.chars().all(|character| {
+            character.is_whitespace() || auto_pairs.iter().any(|(_, closer)| *closer == character)
+        });
+        // a quote straight after a word is an apostrophe
+        let previous = self.text_in(index.saturating_sub(1)..index);
+        let is_apostrophe = value == *closer
+            && index > 0
+            && previous
+                .chars()
+                .any(|character| character.is_alphanumeric() || character == value);
+        if !is_open_after || is_apostrophe {
+            return false;
+        }
+        self.insert_text(index, &format!("{}{}", value, closer));
+        self.pending_closers.push((index + 1, *closer));
+        self.jump_to_index(index + 1);
+        true
+    }
+    // Backspace between the halves of an empty pair that auto-pairing made takes both away in one
+    // undo step
+    fn delete_empty_pair(&mut self) -> bool {
+        let index = self.index;
+        if index == 0
+            || !self
+                .pending_closers
+                .iter()
+                .any(|(closer, _)| *closer == index)
+        {
+            return false;
+        }
+        let around = self.text_in(index - 1..index + 1);
+        let mut around = around.chars();
+        let (Some(opener), Some(closer), None) = (around.next(), around.next(), around.next())
+        else {
+            return false;
+        };
+        if !self.auto_pairs().contains(&(opener, closer)) {
+            return false;
+        }
+        self.delete_range(index - 1..index + 1);
+        true
+    }
+    // a closing bracket typed where the line holds only indentation takes one level of it away,
+    // in the same undo step as the bracket
+    fn dedent_for_closer(&mut self, value: char) -> bool {
+        let is_closer = self.highlighter.as_ref().is_some_and(|highlighter| {
+            highlighter
+                .indent_openers()
+                .iter()
+                .any(|opener| closer_of(opener) == Some(value))
+        });
+        if !is_closer {
+            return false;
+        }
+        let index = self.index;
+        let line = self.logical_line();
+        let line_start = self.logical_line_start(line);
+        let before = self.text_in(line_start..index);
+        if leading_whitespace(&before) != before {
+            return false;
+        }
+        let length = self.indentation.outdent_length(&before);
+        if length == 0 {
+            return false;
+        }
+        let mut replacement: String = before.graphemes(true).skip(length).collect();
+        replacement.push(value);
+        self.shift_pending_closers(
+            line_start,
+            index - line_start,
+            replacement.graphemes(true).count(),
+        );
+        self.text_representation.replace(vec![RegexMatch {
+            start: line_start,
+            length: index - line_start,
+            replacement,
+        }]);
+        self.execute_line_command(ReplaceLinesCommand::new(line, line_start, 1, 1));
+        self.jump_to_index(index - length + 1);
+        true
+    }
+
+    pub fn move_cursor_left(&mut self, offset: usize, final_index: usize) {
+        self.index = final_index;
+        if self.column_number == 0 {
+            if self.row_number > 0 {
+                self.column_number = self
+                    .lines_text_editor
+                    .index(self.page_start + self.row_number - 1)
+                    .unwrap_or_default()
+                    .saturating_sub(offset);
+                self.row_number -= 1;
+            } else if self.page_start > 0 {
+                self.scroll_one_line_up();
+                self.column_number = self
+                    .lines_text_editor
+                    .index(self.page_start)
+                    .unwrap_or_default()
+                    .saturating_sub(offset);
+            }
+        } else {
+            let cursor_moved_left = self.column_number - 1;
+            self.column_number = cursor_moved_left;
+        }
+    }
+    fn move_cursor_down(&mut self) {
+        self.column_number = 0;
+        if self.row_number + 1 >= self.window_height {
+            self.scroll_one_line_down();
+        } else {
+            let cursor_moved_down = self.row_number.saturating_add(1);
+            self.row_number = cursor_moved_down;
+        }
+    }
+
+    pub fn move_cursor_right(&mut self, final_index: usize) {
+        let current_row = self.page_start + self.row_number;
+        if self.column_number
+            == self
+                .lines_text_editor
+                .index(current_row)
+                .unwrap_or_default()
+        {
+            if self.lines_text_editor.index(current_row + 1).is_some() {
+                self.move_cursor_down();
+            }
+            self.index = final_index;
+        } else {
+            self.index = final_index;
+            let cursor_moved_right = self.column_number.saturating_add(1);
+            self.column_number = cursor_moved_right;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+    use crate::rope::Rope;
+
+    fn buffer(content: &str) -> Buffer<Rope> {
+        Buffer::new(
+            "test.txt".to_string(),
+            content.to_string(),
+            Rope::new(content.to_string()),
+            40,
+            10,
+        )
+    }
+    fn rows(lines: &LinesGapBuffer) -> Vec<String> {
+        (0..lines.length())
+            .filter_map(|row| lines.line_at(row))
+            .map(|line| format!("{:?} {}", line.type_of_line(), line.line()))
+            .collect()
+    }
+    fn text(buffer: &Buffer<Rope>) -> String {
+        let mut text = String::new();
+        buffer.text_representation().collect_string(&mut text);
+        text
+    }
+    // the rows an edit leaves behind are the ones the text would have been loaded into
+    fn assert_rows_match_text(buffer: &Buffer<Rope>) {
+        let fresh = LinesGapBuffer::new(&text(buffer), buffer.lines_text_editor().wrap());
+        assert_eq!(rows(buffer.lines_text_editor()), rows(&fresh));
+    }
+
+    #[test]
+    fn edits_in_crlf_text_keep_the_rows_in_step() {
+        let mut buffer = buffer("ab\r\ncd\r\nef");
+        buffer.insert_text(1, "Z");
+        assert_eq!(text(&buffer), "aZb\r\ncd\r\nef");
+        assert_rows_match_text(&buffer);
+        buffer.insert_text(5, "x\r\ny");
+        assert_eq!(text(&buffer), "aZb\r\ncx\r\nyd\r\nef");
+        assert_rows_match_text(&buffer);
+        buffer.delete_range(2..6);
+        assert_rows_match_text(&buffer);
+        buffer.undo();
+        assert_rows_match_text(&buffer);
+    }
+
+    #[test]
+    fn typing_after_enter_at_the_end_goes_on_the_new_line() {
+        for (content, expected) in [("a", "a\nX"), ("a\nb", "a\nb\nX")] {
+            let mut buffer = buffer(content);
+            buffer.move_to_end();
+            buffer.jump_to_new_line();
+            buffer.add_char('X');
+            assert_eq!(text(&buffer), expected);
+            assert_rows_match_text(&buffer);
+            buffer.undo();
+            buffer.undo();
+            assert_eq!(text(&buffer), content);
+            assert_rows_match_text(&buffer);
+        }
+    }
+    #[test]
+    fn text_after_a_loaded_final_newline_gets_a_row() {
+        let mut buffer = buffer("a\n");
+        buffer.insert_text(2, "q");
+        assert_eq!(text(&buffer), "a\nq");
+        assert_rows_match_text(&buffer);
+        assert_eq!(buffer.index(), 3);
+    }
+
+    #[test]
+    fn typing_after_a_paste_at_the_end_goes_after_it() {
+        let mut buffer = buffer("x");
+        buffer.move_to_end();
+        buffer.paste("q\n".to_string());
+        assert_eq!(buffer.index(), 3);
+        buffer.add_char('Z');
+        assert_eq!(text(&buffer), "xq\nZ");
+        assert_rows_match_text(&buffer);
+    }
+    #[test]
+    fn pasted_line_breaks_follow_the_buffer() {
+        let mut buffer = buffer("a\r\nb");
+        buffer.paste("c\nd\re\r\n".to_string());
+        assert_eq!(text(&buffer), "c\r\nd\r\ne\r\na\r\nb");
+        assert_rows_match_text(&buffer);
+    }
+    #[test]
+    fn lines_pasted_into_a_cr_file_get_rows_of_their_own() {
+        let mut buffer = buffer("ab\rcd");
+        buffer.move_to_end();
+        buffer.paste("\re\rf".to_string());
+        assert_eq!(text(&buffer), "ab\rcd\ne\nf");
+        assert_eq!(buffer.lines_text_editor().logical_line_count(), 3);
+        assert_rows_match_text(&buffer);
+    }
+
+    #[test]
+    fn enter_breaks_lines_with_the_buffer_line_ending() {
+        let mut buffer = buffer("ab\r\ncd");
+        buffer.jump_to_index(1);
+        buffer.jump_to_new_line();
+        buffer.add_char('X');
+        assert_eq!(text(&buffer), "a\r\nXb\r\ncd");
+        assert_rows_match_text(&buffer);
+    }
+    #[test]
+    fn enter_in_a_cr_file_starts_a_line_that_is_saved_with_a_cr() {
+        let mut buffer = buffer("ab\rcd");
+        buffer.move_to_end();
+        buffer.jump_to_new_line();
+        buffer.add_char('X');
+        assert_eq!(text(&buffer), "ab\rcd\nX");
+        assert_eq!(buffer.logical_line(), 1);
+        assert_rows_match_text(&buffer);
+        let file_path = env::temp_dir().join("enter_in_a_cr_file.txt");
+        let file_path = file_path.to_string_lossy();
+        buffer.write_to(&file_path).unwrap();
+        assert_eq!(fs::read_to_string(&*file_path).unwrap(), "ab\rcd\rX");
+        fs::remove_file(&*file_path).unwrap();
+    }
+
+    #[test]
+    fn replacing_matches_re_wraps_only_their_lines() {
+        let mut buffer = buffer("one two\nthree two\nfour");
+        buffer.replace(vec![
+            RegexMatch {
+                start: 4,
+                length: 3,
+                replacement: "2".to_string(),
+            },
+            RegexMatch {
+                start: 14,
+                length: 3,
+                replacement: "a\nb".to_string(),
+            },
+        ]);
+        assert_eq!(text(&buffer), "one 2\nthree a\nb\nfour");
+        assert_rows_match_text(&buffer);
+        buffer.undo();
+        assert_eq!(text(&buffer), "one two\nthree two\nfour");
+        assert_rows_match_text(&buffer);
+    }
+
+    #[test]
+    fn bracket_pairs_skip_brackets_in_strings_on_nearby_lines() {
+        let content = "fn f() {\n    g(\")\", 1);\n}\n";
+        let mut buffer = Buffer::new(
+            "test.rs".to_string(),
+            content.to_string(),
+            Rope::new(content.to_string()),
+            40,
+            10,
+        );
+        buffer.refresh_highlighting(buffer.lines_text_editor().length());
+        buffer.jump_to_index(14);
+        assert_eq!(buffer.bracket_pair(), Some((14, 21)));
+        buffer.jump_to_index(7);
+        assert_eq!(buffer.bracket_pair(), Some((7, 24)));
+    }
+
+    #[test]
+    fn typing_a_closer_steps_over_only_auto_inserted_ones() {
+        let mut buffer = buffer("f)");
+        buffer.jump_to_index(1);
+        buffer.add_char(')');
+        assert_eq!(text(&buffer), "f))");
+        buffer.add_char('(');
+        buffer.add_char('x');
+        buffer.add_char(')');
+        assert_eq!(text(&buffer), "f)(x))");
+        assert_eq!(buffer.index(), 5);
+        // once stepped over, the closer is like any other
+        buffer.jump_to_index(4);
+        buffer.add_char(')');
+        assert_eq!(text(&buffer), "f)(x)))");
+    }
+    #[test]
+    fn backspace_takes_away_only_closers_that_auto_pairing_inserted() {
+        let mut paired = buffer("");
+        paired.add_char('(');
+        paired.delete_char();
+        assert_eq!(text(&paired), "");
+        let mut typed = buffer("()");
+        typed.jump_to_index(1);
+        typed.delete_char();
+        assert_eq!(text(&typed), ")");
+    }
+    #[test]
+    fn highlighted_bracket_pairs_are_looked_for_only_a_screen_away() {
+        let content = format!("({})", "x".repeat(1000));
+        let mut buffer = buffer(&content);
+        buffer.jump_to_index(500);
+        assert_eq!(buffer.visible_bracket_pair(), None);
+        assert_eq!(buffer.bracket_pair(), Some((0, 1001)));
+    }
+}
@@ -4,6 +4,8 @@ use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
     gap_buffer::{GapBuffer, LinesGapBuffer},
+    grapheme_reader::{GraphemeReader, line_end},
+    highlight::LineSplice,
     rope::{Node, insert, remove},
     text_representation::TextRepresentation,
 };
@@ -36,7 +38,7 @@ impl DeleteCommand {
 impl Command for InsertCommand {
     fn execute(&self, rope: Box<Node>) -> (Box<Node>, usize) {
         let content = self.content.graphemes(true).collect::<Vec<&str>>();
-        let length = self.content.len();
+        let length = content.len();
 
         (insert(rope, self.index, content), self.index + length)
     }
@@ -59,11 +61,94 @@ impl Command for DeleteCommand {
 
         (insert(rope, self.index, content), self.index + 1)
     }
+    fn cut_content(&self) -> Option<String> {
+        Some(self.cut_content.borrow().clone())
+    }
 }
 
 pub trait Command {
     fn execute(&self, rope: Box<Node>) -> (Box<Node>, usize);
     fn undo(&self, rope: Box<Node>) -> (Box<Node>, usize);
+    // the text the last execution removed, for commands that remove text
+    fn cut_content(&self) -> Option<String> {
+        None
+    }
+}
+
+pub struct ReplaceCommand {
+    index: usize,
+    length_cut: usize,
+    content: String,
+    cut_content: RefCell<String>,
+}
+impl ReplaceCommand {
+    pub fn new(index: usize, length_cut: usize, content: String) -> Self {
+        Self {
+            index,
+            length_cut,
+            content,
+            cut_content: RefCell::new(String::new()),
+        }
+    }
+}
+impl Command for ReplaceCommand {
+    fn execute(&self, rope: Box<Node>) -> (Box<Node>, usize) {
+        let rope = if self.length_cut > 0 {
+            let (rope, cut_content) = remove(rope, self.index, self.length_cut);
+            *self.cut_content.borrow_mut() = cut_content;
+            rope
+        } else {
+            rope
+        };
+        let content = self.content.graphemes(true).collect::<Vec<&str>>();
+        if content.is_empty() {
+            return (rope, self.index);
+        }
+        let content_len = content.len();
+        (insert(rope, self.index, content), self.index + content_len)
+    }
+    fn undo(&self, rope: Box<Node>) -> (Box<Node>, usize) {
+        let content_len = self.content.graphemes(true).count();
+        let rope = if content_len > 0 {
+            remove(rope, self.index, content_len).0
+        } else {
+            rope
+        };
+        let cut_content = &*self.cut_content.borrow();
+        let cut_content = cut_content.graphemes(true).collect::<Vec<&str>>();
+        if cut_content.is_empty() {
+            return (rope, self.index);
+        }
+        (insert(rope, self.index, cut_content), self.index)
+    }
+}
+
+// runs several commands as one step of the undo history
+pub struct CompositeCommand {
+    commands: Vec<Box<dyn Command>>,
+}
+impl CompositeCommand {
+    pub fn new(commands: Vec<Box<dyn Command>>) -> Self {
+        Self { commands }
+    }
+}
+impl Command for CompositeCommand {
+    fn execute(&self, rope: Box<Node>) -> (Box<Node>, usize) {
+        let mut rope = rope;
+        let mut final_index = 0;
+        for command in self.commands.iter() {
+            (rope, final_index) = command.execute(rope);
+        }
+        (rope, final_index)
+    }
+    fn undo(&self, rope: Box<Node>) -> (Box<Node>, usize) {
+        let mut rope = rope;
+        let mut final_index = 0;
+        for command in self.commands.iter().rev() {
+            (rope, final_index) = command.undo(rope);
+        }
+        (rope, final_index)
+    }
 }
 
 pub struct LineMergeTopCommand {
@@ -208,6 +293,20 @@ pub trait LineWidthsCommand {
 pub trait TextEditorLineCommand {
     fn execute(&self, line_command_ctx: LineCommandContext);
     fn undo(&self, line_command_ctx: LineCommandContext);
+    // the logical lines that execute and undo replace, worked out before they run
+    fn lines_touched(&self, _text_editor_lines: &LinesGapBuffer) -> LineSplice {
+        LineSplice::All
+    }
+    fn lines_touched_by_undo(&self, _text_editor_lines: &LinesGapBuffer) -> LineSplice {
+        LineSplice::All
+    }
+}
+fn lines_touched(line: usize, removed: usize, inserted: usize) -> LineSplice {
+    LineSplice::Lines {
+        line,
+        removed,
+        inserted,
+    }
 }
 pub struct AddLineCommand {
     index: usize,
@@ -225,6 +324,12 @@ impl TextEditorLineCommand for AddLineCommand {
     fn undo(&self, line_command_ctx: LineCommandContext) {
         line_command_ctx.text_editor_lines.remove_item(self.index);
     }
+    fn lines_touched(&self, text_editor_lines: &LinesGapBuffer) -> LineSplice {
+        lines_touched(text_editor_lines.logical_line_number(self.index), 0, 1)
+    }
+    fn lines_touched_by_undo(&self, text_editor_lines: &LinesGapBuffer) -> LineSplice {
+        lines_touched(text_editor_lines.logical_line_number(self.index), 1, 0)
+    }
 }
 pub struct RemoveLineCommand {
     index: usize,
@@ -242,6 +347,12 @@ impl TextEditorLineCommand for RemoveLineCommand {
     fn undo(&self, line_command_ctx: LineCommandContext) {
         line_command_ctx.text_editor_lines.add_item(self.index);
     }
+    fn lines_touched(&self, text_editor_lines: &LinesGapBuffer) -> LineSplice {
+        lines_touched(text_editor_lines.logical_line_number(self.index), 1, 0)
+    }
+    fn lines_touched_by_undo(&self, text_editor_lines: &LinesGapBuffer) -> LineSplice {
+        lines_touched(text_editor_lines.logical_line_number(self.index), 0, 1)
+    }
 }
 pub struct SplitLineCommand {
     index: usize,
@@ -267,6 +378,12 @@ impl TextEditorLineCommand for SplitLineCommand {
             .text_editor_lines
             .merge_two_lines(self.index + 1);
     }
+    fn lines_touched(&self, text_editor_lines: &LinesGapBuffer) -> LineSplice {
+        lines_touched(text_editor_lines.logical_line_of(self.index), 1, 2)
+    }
+    fn lines_touched_by_undo(&self, text_editor_lines: &LinesGapBuffer) -> LineSplice {
+        lines_touched(text_editor_lines.logical_line_of(self.index), 2, 1)
+    }
 }
 pub struct MergeLineCommand {
     index: usize,
@@ -292,6 +409,22 @@ impl TextEditorLineCommand for MergeLineCommand {
             .text_editor_lines
             .split_a_line(self.index, self.content_merged_len);
     }
+    fn lines_touched(&self, text_editor_lines: &LinesGapBuffer) -> LineSplice {
+        lines_touched(
+            text_editor_lines
+                .logical_line_of(self.index)
+                .saturating_sub(1),
+            2,
+            1,
+        )
+    }
+    fn lines_touched_by_undo(&self, text_editor_lines: &LinesGapBuffer) -> LineSplice {
+        lines_touched(
+            text_editor_lines.logical_line_of(self.index.saturating_sub(1)),
+            1,
+            2,
+        )
+    }
 }
 pub struct RemoveFromLineCommand {
     index: usize,
@@ -341,6 +474,12 @@ impl TextEditorLineCommand for RemoveFromLineCommand {
             line_command_ctx.text_representation,
         );
     }
+    fn lines_touched(&self, text_editor_lines: &LinesGapBuffer) -> LineSplice {
+        lines_touched(text_editor_lines.logical_line_of(self.index), 1, 1)
+    }
+    fn lines_touched_by_undo(&self, text_editor_lines: &LinesGapBuffer) -> LineSplice {
+        lines_touched(text_editor_lines.logical_line_of(self.index), 1, 1)
+    }
 }
 pub struct InsertIntoLineCommand {
     index: usize,
@@ -394,6 +533,84 @@ impl TextEditorLineCommand for InsertIntoLineCommand {
             line_command_ctx.text_editor_lines.clear(self.index);
         }
     }
+    fn lines_touched(&self, text_editor_lines: &LinesGapBuffer) -> LineSplice {
+        lines_touched(text_editor_lines.logical_line_of(self.index), 1, 1)
+    }
+    fn lines_touched_by_undo(&self, text_editor_lines: &LinesGapBuffer) -> LineSplice {
+        lines_touched(text_editor_lines.logical_line_of(self.index), 1, 1)
+    }
+}
+// re-wraps only the logical lines an edit went through, `removed` of them before it and `inserted`
+// after it, all starting at the rope index `line_start`
+pub struct ReplaceLinesCommand {
+    line: usize,
+    line_start: usize,
+    removed: usize,
+    inserted: usize,
+}
+impl ReplaceLinesCommand {
+    pub fn new(line: usize, line_start: usize, removed: usize, inserted: usize) -> Self {
+        Self {
+            line,
+            line_start,
+            removed,
+            inserted,
+        }
+    }
+}
+impl TextEditorLineCommand for ReplaceLinesCommand {
+    fn execute(&self, line_command_ctx: LineCommandContext) {
+        let lines = logical_lines(
+            line_command_ctx.text_representation,
+            self.line_start,
+            self.inserted,
+        );
+        line_command_ctx
+            .text_editor_lines
+            .replace_logical_lines(self.line, self.removed, &lines);
+    }
+    fn undo(&self, line_command_ctx: LineCommandContext) {
+        let lines = logical_lines(
+            line_command_ctx.text_representation,
+            self.line_start,
+            self.removed,
+        );
+        line_command_ctx
+            .text_editor_lines
+            .replace_logical_lines(self.line, self.inserted, &lines);
+    }
+    fn lines_touched(&self, _text_editor_lines: &LinesGapBuffer) -> LineSplice {
+        lines_touched(self.line, self.removed, self.inserted)
+    }
+    fn lines_touched_by_undo(&self, _text_editor_lines: &LinesGapBuffer) -> LineSplice {
+        lines_touched(self.line, self.inserted, self.removed)
+    }
+}
+// the text of `count` logical lines from the rope index `start`, without their line breaks,
+// which are matched as graphemes so that a "\r\n" counts as one, and with the empty line after
+// a final newline when the count reaches it
+fn logical_lines(
+    text_representation: &dyn TextRepresentation,
+    start: usize,
+    count: usize,
+) -> Vec<String> {
+    let mut reader = GraphemeReader::new(text_representation);
+    let length = reader.len();
+    let mut lines = Vec::with_capacity(count);
+    let mut position = start;
+    for _ in 0..count {
+        let end = line_end(&mut reader, position);
+        let mut line = String::new();
+        if end > position {
+            text_representation.collect_substring(&mut line, (position, end - 1));
+        }
+        lines.push(line);
+        if end == length {
+            break;
+        }
+        position = end + 1;
+    }
+    lines
 }
 pub struct LineCommandContext<'a> {
     text_editor_lines: &'a mut LinesGapBuffer,
@@ -410,4 +627,3 @@ impl<'a> LineCommandContext<'a> {
         }
     }
 }
-
@@ -0,0 +1,394 @@
+use std::{fs, path::Path};
+
+use crate::status_line::parse_segments;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExCommand {
+    Write {
+        file_path: Option<String>,
+    },
+    Quit {
+        force: bool,
+    },
+    WriteQuit,
+    Edit {
+        file_path: String,
+    },
+    Goto {
+        line: Option<usize>,
+        column: Option<usize>,
+    },
+    GotoOffset {
+        offset: usize,
+    },
+    Set {
+        option: String,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Argument {
+    None,
+    OptionalPath,
+    Path,
+    Number,
+    Position,
+    Option,
+}
+
+pub struct CommandSpec {
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    pub argument: Argument,
+    pub forceable: bool,
+}
+impl CommandSpec {
+    fn is_called(&self, name: &str) -> bool {
+        self.name == name || self.aliases.contains(&name)
+    }
+}
+
+// every command the prompt knows, looked up by name or by any of the short aliases
+pub const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "write",
+        aliases: &["w"],
+        argument: Argument::OptionalPath,
+        forceable: false,
+    },
+    CommandSpec {
+        name: "quit",
+        aliases: &["q"],
+        argument: Argument::None,
+        forceable: true,
+    },
+    CommandSpec {
+        name: "wq",
+        aliases: &["x"],
+        argument: Argument::None,
+        forceable: false,
+    },
+    CommandSpec {
+        name: "edit",
+        aliases: &["e"],
+        argument: Argument::Path,
+        forceable: false,
+    },
+    CommandSpec {
+        name: "goto",
+        aliases: &["go"],
+        argument: Argument::Position,
+        forceable: false,
+    },
+    CommandSpec {
+        name: "offset",
+        aliases: &["of"],
+        argument: Argument::Number,
+        forceable: false,
+    },
+    CommandSpec {
+        name: "set",
+        aliases: &["se"],
+        argument: Argument::Option,
+        forceable: false,
+    },
+];
+
+pub const SET_OPTIONS: &[&str] = &[
+    "wrap",
+    "nowrap",
+    "linebreak",
+    "nolinebreak",
+    "breakindent",
+    "nobreakindent",
+    "expandtab",
+    "noexpandtab",
+    "tabwidth",
+    "statusline",
+];
+
+pub fn find_command(name: &str) -> Option<&'static CommandSpec> {
+    COMMANDS.iter().find(|spec| spec.is_called(name))
+}
+
+fn split_command(input: &str) -> (&str, &str) {
+    let input = input.trim_start().trim_start_matches(':');
+    match input.find(char::is_whitespace) {
+        Some(position) => (&input[..position], input[position..].trim()),
+        None => (input, ""),
+    }
+}
+
+pub fn parse(input: &str) -> Result<ExCommand, String> {
+    let (name, argument) = split_command(input);
+    if name.is_empty() {
+        return Err("no command given".to_string());
+    }
+    // a bare number goes to that line
+    if let Ok(line) = name.parse::<usize>() {
+        return Ok(ExCommand::Goto {
+            line: Some(line),
+            column: None,
+        });
+    }
+    let (name, force) = match name.strip_suffix('!') {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+    let spec = find_command(name).ok_or_else(|| format!("not a command: {}", name))?;
+    if force && !spec.forceable {
+        return Err(format!("{} does not take !", spec.name));
+    }
+    match (spec.argument, argument.is_empty()) {
+        (Argument::None, false) => return Err(format!("{} takes no argument", spec.name)),
+        (Argument::Path, true) => return Err(format!("{} needs a file path", spec.name)),
+        (Argument::Number, true) => return Err(format!("{} needs a number", spec.name)),
+        (Argument::Position, true) => {
+            return Err(format!(
+                "{} needs a line, line:column or :column",
+                spec.name
+            ));
+        }
+        (Argument::Option, true) => {
+            return Err(format!(
+                "{} needs one of the options {}",
+                spec.name,
+                SET_OPTIONS.join(", ")
+            ));
+        }
+        _ => {}
+    }
+    let command = match spec.name {
+        "write" => ExCommand::Write {
+            file_path: (!argument.is_empty()).then(|| argument.to_string()),
+        },
+        "quit" => ExCommand::Quit { force },
+        "wq" => ExCommand::WriteQuit,
+        "edit" => ExCommand::Edit {
+            file_path: argument.to_string(),
+        },
+        "goto" => parse_position(argument)?,
+        "offset" => ExCommand::GotoOffset {
+            offset: parse_number(argument)?,
+        },
+        "set" => {
+            let option = argument.trim_end_matches('?');
+            let (option, value) = match option.split_once('=') {
+                Some((option, value)) => (option, Some(value)),
+                None => (option, None),
+            };
+            if !SET_OPTIONS.contains(&option) {
+                return Err(format!("unknown option: {}", option));
+            }
+            // only the width and the status line take a value, and they have to have one unless
+            // they are asked about
+            match (option, value) {
+                ("tabwidth", Some(value)) if parse_number(value)? == 0 => {
+                    return Err("tabwidth has to be at least 1".to_string());
+                }
+                ("tabwidth", None) if !argument.ends_with('?') => {
+                    return Err("tabwidth needs a value, like tabwidth=4".to_string());
+                }
+                ("statusline", Some(value)) => {
+                    parse_segments(value)?;
+                }
+                ("statusline", None) if !argument.ends_with('?') => {
+                    return Err(
+                        "statusline needs a value, like statusline=path,position".to_string()
+                    );
+                }
+                ("tabwidth", _) | (_, None) => {}
+                (option, Some(_)) => return Err(format!("{} takes no value", option)),
+            }
+            ExCommand::Set {
+                option: argument.to_string(),
+            }
+        }
+        name => return Err(format!("not a command: {}", name)),
+    };
+    Ok(command)
+}
+
+fn parse_number(argument: &str) -> Result<usize, String> {
+    argument
+        .parse()
+        .map_err(|_| format!("not a number: {}", argument))
+}
+
+// `120`, `120:8` or `:8`, counted from one like the line numbers in the gutter
+fn parse_position(argument: &str) -> Result<ExCommand, String> {
+    let (line, column) = match argument.split_once(':') {
+        Some((line, column)) => (line, Some(column)),
+        None => (argument, None),
+    };
+    let line = match line.is_empty() {
+        true => None,
+        false => Some(parse_number(line)?),
+    };
+    let column = column.map(parse_number).transpose()?;
+    Ok(ExCommand::Goto { line, column })
+}
+
+// every way the input could be finished, each one a whole new input
+pub fn completions(input: &str) -> Vec<String> {
+    let (name, argument) = split_command(input);
+    if !input.trim_start().contains(char::is_whitespace) {
+        return COMMANDS
+            .iter()
+            .map(|spec| spec.name)
+            .filter(|command_name| command_name.starts_with(name))
+            .map(str::to_string)
+            .collect();
+    }
+    let Some(spec) = find_command(name.trim_end_matches('!')) else {
+        return vec![];
+    };
+    let candidates = match spec.argument {
+        Argument::Path | Argument::OptionalPath => complete_path(argument),
+        Argument::Option => SET_OPTIONS
+            .iter()
+            .filter(|option| option.starts_with(argument))
+            .map(|option| option.to_string())
+            .collect(),
+        Argument::None | Argument::Number | Argument::Position => vec![],
+    };
+    candidates
+        .into_iter()
+        .map(|candidate| format!("{} {}", name, candidate))
+        .collect()
+}
+
+// the entries of the directory named so far whose names start with what follows the last slash
+fn complete_path(partial: &str) -> Vec<String> {
+    let (directory, prefix) = match partial.rfind('/') {
+        Some(position) => partial.split_at(position + 1),
+        None => ("", partial),
+    };
+    let read_from = if directory.is_empty() { "." } else { directory };
+    let Ok(entries) = fs::read_dir(Path::new(read_from)) else {
+        return vec![];
+    };
+    let mut candidates: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            // hidden files only come up once their dot has been typed
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            let is_directory = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+            Some(format!(
+                "{}{}{}",
+                directory,
+                name,
+                if is_directory { "/" } else { "" }
+            ))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
+
+// the text typed after `:`, with tab completion and the commands entered earlier in the session
+#[derive(Default)]
+pub struct CommandLine {
+    input: String,
+    history: Vec<String>,
+    history_index: Option<usize>,
+    draft: String,
+    completions: Vec<String>,
+    completion_index: usize,
+}
+impl CommandLine {
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+    // the candidates of the current completion, without the command name in front of arguments
+    pub fn completion_labels(&self) -> Vec<&str> {
+        self.completions
+            .iter()
+            .map(|completion| match split_command(completion) {
+                (name, "") => name,
+                (_, argument) => argument,
+            })
+            .collect()
+    }
+    pub fn start(&mut self) {
+        self.input.clear();
+        self.history_index = None;
+        self.completions.clear();
+    }
+    pub fn push(&mut self, value: char) {
+        self.input.push(value);
+        self.stop_browsing();
+    }
+    pub fn push_str(&mut self, text: &str) {
+        self.input.push_str(text);
+        self.stop_browsing();
+    }
+    pub fn pop(&mut self) {
+        self.stop_browsing();
+        self.input.pop();
+    }
+    fn stop_browsing(&mut self) {
+        self.history_index = None;
+        self.completions.clear();
+    }
+    // the first Tab fills in the first candidate and later ones cycle through the rest
+    pub fn complete(&mut self, backwards: bool) {
+        if self.completions.is_empty() {
+            self.completions = completions(&self.input);
+            self.completion_index = match backwards {
+                true => self.completions.len().saturating_sub(1),
+                false => 0,
+            };
+        } else if backwards {
+            self.completion_index =
+                (self.completion_index + self.completions.len() - 1) % self.completions.len();
+        } else {
+            self.completion_index = (self.completion_index + 1) % self.completions.len();
+        }
+        if let Some(completion) = self.completions.get(self.completion_index) {
+            self.input = completion.clone();
+        }
+        // a single candidate is final, so that the next Tab completes from it
+        if self.completions.len() == 1 {
+            self.completions.clear();
+        }
+    }
+    pub fn history_previous(&mut self) {
+        let index = match self.history_index {
+            Some(index) => index.saturating_sub(1),
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = self.input.clone();
+                self.history.len() - 1
+            }
+        };
+        self.history_index = Some(index);
+        self.input = self.history[index].clone();
+        self.completions.clear();
+    }
+    pub fn history_next(&mut self) {
+        let Some(index) = self.history_index else {
+            return;
+        };
+        if index + 1 < self.history.len() {
+            self.history_index = Some(index + 1);
+            self.input = self.history[index + 1].clone();
+        } else {
+            self.history_index = None;
+            self.input = std::mem::take(&mut self.draft);
+        }
+        self.completions.clear();
+    }
+    // hands back the entered command, remembering it for the rest of the session
+    pub fn submit(&mut self) -> String {
+        let input = std::mem::take(&mut self.input);
+        if !input.trim().is_empty() && self.history.last() != Some(&input) {
+            self.history.push(input.clone());
+        }
+        self.stop_browsing();
+        input
+    }
+}
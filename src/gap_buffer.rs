@@ -1,10 +1,24 @@
-use ratatui::text::Line;
+use std::{cmp::min, ops::Range};
+
+use ratatui::{
+    style::Style,
+    text::{Line, Span},
+};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 use crate::{
     app::get_line_widths,
-    text_editor_line::{TextEditorLine, TypeOfLine, generate_lines},
+    text_editor_line::{
+        TextEditorLine, TypeOfLine, WrapOptions, display_width, generate_lines, grapheme_width,
+        wrap_line,
+    },
     text_representation::TextRepresentation,
 };
+
+// drawn in the gutter beside the rows a long line carries on over
+const WRAP_INDICATOR: &str = "↪";
+
 #[derive(Default)]
 pub struct GapBuffer {
     buffer: Vec<usize>,
@@ -319,20 +333,26 @@ pub struct LinesGapBuffer {
     buffer: Vec<TextEditorLine>,
     starting_of_gap: usize,
     ending_of_gap: usize,
-    window_width: usize,
+    wrap: WrapOptions,
 }
 
 impl LinesGapBuffer {
-    pub fn new(content: &str, window_width: usize) -> Self {
-        let (buffer, starting_of_gap, ending_of_gap) = generate_lines(content, window_width);
+    pub fn new(content: &str, wrap: WrapOptions) -> Self {
+        let (buffer, starting_of_gap, ending_of_gap) = generate_lines(content, wrap);
         Self {
             buffer,
             starting_of_gap,
             ending_of_gap,
-            window_width,
+            wrap,
         }
     }
 
+    pub fn rebuild(&mut self, text_representation: &dyn TextRepresentation) {
+        let mut content = String::new();
+        text_representation.collect_string(&mut content);
+        *self = Self::new(&content, self.wrap);
+    }
+
     pub fn index(&self, index: usize) -> Option<usize> {
         if index >= self.buffer.len() - ((self.ending_of_gap - self.starting_of_gap) + 1) {
             return None;
@@ -376,31 +396,102 @@ impl LinesGapBuffer {
         }
     }
     pub fn find_where_rope_index_fits(&self, rope_index: usize) -> (usize, usize) {
-        let mut rope_index = rope_index as i32;
-        for i in 0..self.starting_of_gap {
-            rope_index -= (self.buffer[i].get_line_length()) as i32;
-            if rope_index <= 0 {
-                return (
-                    i,
-                    (self.buffer[i].get_line_length() - rope_index.unsigned_abs() as usize),
-                );
+        let mut rope_index = rope_index as i64;
+        let mut last_row = (0, 0);
+        for row in 0..self.length() {
+            let line = self.line_at(row).unwrap();
+            let line_length = line.get_line_length() as i64;
+            rope_index -= line_length;
+            let ends_with_newline =
+                !matches!(line.type_of_line(), TypeOfLine::Parent | TypeOfLine::Child);
+            if rope_index < 0 || (rope_index == 0 && ends_with_newline) {
+                return (row, (line_length + rope_index) as usize);
             }
-            rope_index -= 1;
+            rope_index -= (line.get_line_length_for_offset() as i64) - line_length;
+            last_row = (row, line_length as usize);
         }
-        let before_index = self.starting_of_gap;
-        for i in self.ending_of_gap + 1..self.buffer.len() {
-            let after_index = i - self.ending_of_gap;
-            let final_index = before_index + after_index - 1;
-            rope_index -= (self.buffer[i].get_line_length()) as i32;
-            if rope_index <= 0 {
-                return (
-                    final_index,
-                    (self.buffer[i].get_line_length() - rope_index.unsigned_abs() as usize),
+        last_row
+    }
+    pub fn line_at(&self, index: usize) -> Option<&TextEditorLine> {
+        if index >= self.length() {
+            return None;
+        }
+        if index < self.starting_of_gap {
+            Some(&self.buffer[index])
+        } else {
+            let offset = index - self.starting_of_gap + 1;
+            Some(&self.buffer[self.ending_of_gap + offset])
+        }
+    }
+    pub fn logical_line_number(&self, index: usize) -> usize {
+        (0..index.min(self.length()))
+            .filter(|row| {
+                matches!(
+                    self.get_line_type(*row),
+                    Some(TypeOfLine::Parent | TypeOfLine::Independent)
+                )
+            })
+            .count()
+    }
+    // the logical line that a row belongs to, wrapped rows sharing the line of their parent
+    pub fn logical_line_of(&self, index: usize) -> usize {
+        self.logical_line_number(index + 1).saturating_sub(1)
+    }
+    // swaps the rows of `removed` logical lines starting at `line` for the wrapped rows of `lines`
+    pub fn replace_logical_lines(&mut self, line: usize, removed: usize, lines: &[String]) {
+        let first_row = self
+            .first_row_of_logical_line(line)
+            .unwrap_or(self.length());
+        let end_row = self
+            .first_row_of_logical_line(line + removed)
+            .unwrap_or(self.length());
+        for _ in first_row..end_row {
+            self.remove_item(first_row);
+        }
+        let mut row = first_row;
+        for text in lines {
+            for wrapped_line in wrap_line(text, self.wrap) {
+                self.add_item_with_content(
+                    row,
+                    wrapped_line.line().to_string(),
+                    wrapped_line.type_of_line().clone(),
                 );
+                row += 1;
             }
-            rope_index -= 1;
         }
-        (0, 0)
+    }
+    pub fn logical_line_count(&self) -> usize {
+        self.logical_line_number(self.length())
+    }
+    // the first of the wrapped rows that a logical line is made of
+    pub fn first_row_of_logical_line(&self, line: usize) -> Option<usize> {
+        (0..self.length())
+            .filter(|row| {
+                matches!(
+                    self.get_line_type(*row),
+                    Some(TypeOfLine::Parent | TypeOfLine::Independent)
+                )
+            })
+            .nth(line)
+    }
+    // the length of the logical line starting at a row, summed over its wrapped rows
+    pub fn logical_line_length(&self, first_row: usize) -> usize {
+        let mut length = 0;
+        for row in first_row..self.length() {
+            let Some(line) = self.line_at(row) else {
+                break;
+            };
+            if row > first_row
+                && matches!(
+                    line.type_of_line(),
+                    TypeOfLine::Parent | TypeOfLine::Independent
+                )
+            {
+                break;
+            }
+            length += line.get_line_length();
+        }
+        length
     }
     pub fn find_where_rope_index_fits_for_current_page(
         &self,
@@ -653,13 +744,73 @@ impl LinesGapBuffer {
             let new_index = self.ending_of_gap + index_offset;
             self.buffer[new_index].change_line(bounds, text_representation);
         }
-        if self.index(index).unwrap_or_default() > self.window_width {
-            self.split_a_line_due_to_word_wrap(index, self.window_width);
+        if self.overflows(index) {
+            self.split_a_line_due_to_word_wrap(index, self.wrap_position(index));
         }
 
         Some(())
     }
-    fn is_independent(&self, index: usize) -> bool {
+    // the columns a continuation row is pushed in by under a hanging indent, and none for the
+    // first row of a line
+    pub fn hanging_indent(&self, index: usize) -> usize {
+        if !self.wrap.hanging_indent
+            || !matches!(
+                self.get_line_type(index),
+                Some(TypeOfLine::Child | TypeOfLine::Terminator)
+            )
+        {
+            return 0;
+        }
+        (0..index)
+            .rev()
+            .find(|row| matches!(self.get_line_type(*row), Some(TypeOfLine::Parent)))
+            .and_then(|row| self.line_at(row))
+            .map_or(0, |line| self.wrap.continuation_indent(line.line()))
+    }
+    // the columns left for the text of a row once its hanging indent is taken out
+    fn row_width(&self, index: usize) -> usize {
+        self.wrap.width - self.hanging_indent(index)
+    }
+    // whether a row has grown wider than the window and has to wrap
+    fn overflows(&self, index: usize) -> bool {
+        self.wrap.enabled
+            && self
+                .line_at(index)
+                .is_some_and(|line| line.display_width(self.wrap.tab_width) > self.row_width(index))
+    }
+    // how many graphemes of a row stay on it when it wraps
+    fn wrap_position(&self, index: usize) -> usize {
+        self.line_at(index).map_or(self.wrap.width, |line| {
+            line.wrap_position(
+                self.row_width(index),
+                self.wrap.tab_width,
+                self.wrap.at_words,
+            )
+        })
+    }
+    // whether a row still fits in the window once text goes in at a column of it
+    pub fn fits_with_insert(&self, index: usize, column: usize, text: &str) -> bool {
+        let Some(line) = self.line_at(index) else {
+            return false;
+        };
+        if !self.wrap.enabled {
+            return true;
+        }
+        let mut graphemes = line.line().graphemes(true);
+        let mut inserted: String = graphemes.by_ref().take(column).collect();
+        inserted.push_str(text);
+        inserted.extend(graphemes);
+        display_width(&inserted, self.wrap.tab_width) <= self.row_width(index)
+    }
+    // the screen column of a column of a row, with tabs drawn up to the next tab stop and
+    // continuation rows starting after their hanging indent
+    pub fn display_column(&self, index: usize, column: usize) -> usize {
+        self.hanging_indent(index)
+            + self.line_at(index).map_or(column, |line| {
+                line.display_column(column, self.wrap.tab_width)
+            })
+    }
+    pub fn is_independent(&self, index: usize) -> bool {
         if index >= self.buffer.len() - ((self.ending_of_gap - self.starting_of_gap) + 1) {
             return true;
         }
@@ -672,7 +823,7 @@ impl LinesGapBuffer {
             self.buffer[new_index].is_independent()
         }
     }
-    fn get_line_type(&self, index: usize) -> Option<&TypeOfLine> {
+    pub fn get_line_type(&self, index: usize) -> Option<&TypeOfLine> {
         if index >= self.buffer.len() - ((self.ending_of_gap - self.starting_of_gap) + 1) {
             return None;
         }
@@ -703,10 +854,13 @@ impl LinesGapBuffer {
             let current_line_type = self.buffer[index].type_of_line();
             match current_line_type {
                 TypeOfLine::Parent => {
-                    match self.increase_line(index + 1, &cut_content) {
+                    match self.prepend_to_line(index + 1, &cut_content) {
                         Some(_) => {
-                            if self.index(index + 1).unwrap_or_default() > self.window_width {
-                                self.split_a_line_due_to_word_wrap(index + 1, self.window_width);
+                            if self.overflows(index + 1) {
+                                self.split_a_line_due_to_word_wrap(
+                                    index + 1,
+                                    self.wrap_position(index + 1),
+                                );
                             }
                         }
                         None => {
@@ -719,10 +873,13 @@ impl LinesGapBuffer {
                     };
                 }
                 TypeOfLine::Child => {
-                    match self.increase_line(index + 1, &cut_content) {
+                    match self.prepend_to_line(index + 1, &cut_content) {
                         Some(_) => {
-                            if self.index(index + 1).unwrap_or_default() > self.window_width {
-                                self.split_a_line_due_to_word_wrap(index + 1, self.window_width);
+                            if self.overflows(index + 1) {
+                                self.split_a_line_due_to_word_wrap(
+                                    index + 1,
+                                    self.wrap_position(index + 1),
+                                );
                             }
                         }
                         None => {
@@ -751,10 +908,13 @@ impl LinesGapBuffer {
             let current_line_type = self.buffer[new_index].type_of_line();
             match current_line_type {
                 TypeOfLine::Parent => {
-                    match self.increase_line(index + 1, &cut_content) {
+                    match self.prepend_to_line(index + 1, &cut_content) {
                         Some(_) => {
-                            if self.index(index + 1).unwrap_or_default() > self.window_width {
-                                self.split_a_line_due_to_word_wrap(index + 1, self.window_width);
+                            if self.overflows(index + 1) {
+                                self.split_a_line_due_to_word_wrap(
+                                    index + 1,
+                                    self.wrap_position(index + 1),
+                                );
                             }
                         }
                         None => {
@@ -767,10 +927,13 @@ impl LinesGapBuffer {
                     };
                 }
                 TypeOfLine::Child => {
-                    match self.increase_line(index + 1, &cut_content) {
+                    match self.prepend_to_line(index + 1, &cut_content) {
                         Some(_) => {
-                            if self.index(index + 1).unwrap_or_default() > self.window_width {
-                                self.split_a_line_due_to_word_wrap(index + 1, self.window_width);
+                            if self.overflows(index + 1) {
+                                self.split_a_line_due_to_word_wrap(
+                                    index + 1,
+                                    self.wrap_position(index + 1),
+                                );
                             }
                         }
                         None => {
@@ -809,10 +972,13 @@ impl LinesGapBuffer {
             match current_line_type {
                 TypeOfLine::Parent => {
                     self.buffer[index].set_type_of_line(TypeOfLine::Independent);
-                    match self.increase_line(index + 1, &cut_content) {
+                    match self.prepend_to_line(index + 1, &cut_content) {
                         Some(_) => {
-                            if self.index(index + 1).unwrap_or_default() > self.window_width {
-                                self.split_a_line_due_to_word_wrap(index + 1, self.window_width);
+                            if self.overflows(index + 1) {
+                                self.split_a_line_due_to_word_wrap(
+                                    index + 1,
+                                    self.wrap_position(index + 1),
+                                );
                             }
                         }
                         None => {
@@ -826,10 +992,13 @@ impl LinesGapBuffer {
                 }
                 TypeOfLine::Child => {
                     self.buffer[index].set_type_of_line(TypeOfLine::Terminator);
-                    match self.increase_line(index + 1, &cut_content) {
+                    match self.prepend_to_line(index + 1, &cut_content) {
                         Some(_) => {
-                            if self.index(index + 1).unwrap_or_default() > self.window_width {
-                                self.split_a_line_due_to_word_wrap(index + 1, self.window_width);
+                            if self.overflows(index + 1) {
+                                self.split_a_line_due_to_word_wrap(
+                                    index + 1,
+                                    self.wrap_position(index + 1),
+                                );
                             }
                         }
                         None => {
@@ -854,10 +1023,13 @@ impl LinesGapBuffer {
             match current_line_type {
                 TypeOfLine::Parent => {
                     self.buffer[new_index].set_type_of_line(TypeOfLine::Independent);
-                    match self.increase_line(index + 1, &cut_content) {
+                    match self.prepend_to_line(index + 1, &cut_content) {
                         Some(_) => {
-                            if self.index(index + 1).unwrap_or_default() > self.window_width {
-                                self.split_a_line_due_to_word_wrap(index + 1, self.window_width);
+                            if self.overflows(index + 1) {
+                                self.split_a_line_due_to_word_wrap(
+                                    index + 1,
+                                    self.wrap_position(index + 1),
+                                );
                             }
                         }
                         None => {
@@ -871,10 +1043,13 @@ impl LinesGapBuffer {
                 }
                 TypeOfLine::Child => {
                     self.buffer[new_index].set_type_of_line(TypeOfLine::Terminator);
-                    match self.increase_line(index + 1, &cut_content) {
+                    match self.prepend_to_line(index + 1, &cut_content) {
                         Some(_) => {
-                            if self.index(index + 1).unwrap_or_default() > self.window_width {
-                                self.split_a_line_due_to_word_wrap(index + 1, self.window_width);
+                            if self.overflows(index + 1) {
+                                self.split_a_line_due_to_word_wrap(
+                                    index + 1,
+                                    self.wrap_position(index + 1),
+                                );
                             }
                         }
                         None => {
@@ -903,8 +1078,8 @@ impl LinesGapBuffer {
         }
         let removed_line = self.remove_item(index)?;
         self.increase_line(index - 1, removed_line.line());
-        if self.index(index - 1).unwrap_or_default() > self.window_width {
-            self.split_a_line_due_to_word_wrap(index - 1, self.window_width);
+        if self.overflows(index - 1) {
+            self.split_a_line_due_to_word_wrap(index - 1, self.wrap_position(index - 1));
         }
         Some(())
     }
@@ -951,6 +1126,24 @@ impl LinesGapBuffer {
         Some(value_to_be_removed)
     }
 
+    // the graphemes cut off the end of a row go in front of the next row of the same line
+    fn prepend_to_line(&mut self, index: usize, content: &str) -> Option<()> {
+        if self.ending_of_gap < self.starting_of_gap {
+            self.resize();
+        }
+        if index >= self.buffer.len() - ((self.ending_of_gap - self.starting_of_gap) + 1) {
+            return None;
+        }
+        let index = if index < self.starting_of_gap {
+            index
+        } else {
+            self.ending_of_gap + index - self.starting_of_gap + 1
+        };
+        let line = self.buffer[index].line();
+        let type_of_line = self.buffer[index].type_of_line().clone();
+        self.buffer[index] = TextEditorLine::new(format!("{}{}", content, line), type_of_line);
+        Some(())
+    }
     fn increase_line(&mut self, index: usize, content: &str) -> Option<()> {
         if self.ending_of_gap < self.starting_of_gap {
             self.resize();
@@ -983,14 +1176,14 @@ impl LinesGapBuffer {
                     line_number += 1;
                 }
                 TypeOfLine::Child => {
-                    lines.push(Line::raw(""));
+                    lines.push(Line::raw(WRAP_INDICATOR));
                 }
                 TypeOfLine::Independent => {
                     lines.push(Line::raw(line_number.to_string()));
                     line_number += 1;
                 }
                 TypeOfLine::Terminator => {
-                    lines.push(Line::raw(""));
+                    lines.push(Line::raw(WRAP_INDICATOR));
                 }
             }
         }
@@ -1001,14 +1194,14 @@ impl LinesGapBuffer {
                     line_number += 1;
                 }
                 TypeOfLine::Child => {
-                    lines.push(Line::raw(""));
+                    lines.push(Line::raw(WRAP_INDICATOR));
                 }
                 TypeOfLine::Independent => {
                     lines.push(Line::raw(line_number.to_string()));
                     line_number += 1;
                 }
                 TypeOfLine::Terminator => {
-                    lines.push(Line::raw(""));
+                    lines.push(Line::raw(WRAP_INDICATOR));
                 }
             }
         }
@@ -1019,63 +1212,21 @@ impl LinesGapBuffer {
         page_start: usize,
         page_height: usize,
     ) -> Vec<Line<'_>> {
-        let mut page_height = page_height;
-        let mut line_number = page_start;
+        let mut line_number = self.logical_line_number(page_start) + 1;
         let mut lines = Vec::new();
-        let starting_point = if page_start < self.starting_of_gap {
-            page_start
-        } else {
-            let distance_from_ending = page_start - self.starting_of_gap + 1;
-            self.ending_of_gap + distance_from_ending
-        };
-        for i in starting_point..self.starting_of_gap {
-            match self.buffer[i].type_of_line() {
-                TypeOfLine::Parent => {
-                    lines.push(Line::raw(line_number.to_string()));
-                    line_number += 1;
-                }
-                TypeOfLine::Child => {
-                    lines.push(Line::raw(""));
-                }
-                TypeOfLine::Independent => {
-                    lines.push(Line::raw(line_number.to_string()));
-                    line_number += 1;
-                }
-                TypeOfLine::Terminator => {
-                    lines.push(Line::raw(""));
-                }
-            }
-            page_height -= 1;
-            if page_height == 0 {
-                return lines;
-            }
-        }
-        let starting_point_after_gap = if starting_point < self.starting_of_gap {
-            self.ending_of_gap + 1
-        } else {
-            starting_point
-        };
-        for i in starting_point_after_gap..self.length() {
-            match self.buffer[i].type_of_line() {
-                TypeOfLine::Parent => {
+        for row in page_start..min(page_start + page_height, self.length()) {
+            match self.get_line_type(row) {
+                Some(TypeOfLine::Parent) | Some(TypeOfLine::Independent) => {
                     lines.push(Line::raw(line_number.to_string()));
                     line_number += 1;
                 }
-                TypeOfLine::Child => {
-                    lines.push(Line::raw(""));
+                Some(_) => {
+                    lines.push(Line::raw(WRAP_INDICATOR));
                 }
-                TypeOfLine::Independent => {
-                    lines.push(Line::raw(line_number.to_string()));
-                    line_number += 1;
-                }
-                TypeOfLine::Terminator => {
+                None => {
                     lines.push(Line::raw(""));
                 }
             }
-            page_height -= 1;
-            if page_height == 0 {
-                return lines;
-            }
         }
         lines
     }
@@ -1093,35 +1244,30 @@ impl LinesGapBuffer {
         &self,
         page_start: usize,
         page_height: usize,
+        page_index: usize,
+        scroll_column: usize,
+        highlights: &[(Range<usize>, Style)],
     ) -> Vec<Line<'_>> {
-        let mut page_height = page_height;
-        let mut lines = Vec::new();
-        let starting_point = if page_start < self.starting_of_gap {
-            page_start
-        } else {
-            let distance_from_ending = page_start - self.starting_of_gap + 1;
-            self.ending_of_gap + distance_from_ending
+        let mut row_start = page_index;
+        // wrapped rows always fit, so only unwrapped ones are scrolled sideways
+        let scroll_column = match self.wrap.enabled {
+            true => 0,
+            false => scroll_column,
         };
-        for i in starting_point..self.starting_of_gap {
-            lines.push(Line::raw(self.buffer[i].line()));
-            page_height -= 1;
-            if page_height == 0 {
-                return lines;
-            }
-        }
-        let starting_point_after_gap = if starting_point < self.starting_of_gap {
-            self.ending_of_gap + 1
-        } else {
-            starting_point
-        };
-        for i in starting_point_after_gap..self.length() {
-            lines.push(Line::raw(self.buffer[i].line()));
-            page_height -= 1;
-            if page_height == 0 {
-                return lines;
-            }
-        }
-        lines
+        (page_start..min(page_start + page_height, self.length()))
+            .filter_map(|row| Some((self.hanging_indent(row), self.line_at(row)?)))
+            .map(|(hanging_indent, line)| {
+                let mut spans = style_line(line.line(), row_start, highlights, self.wrap.tab_width);
+                if hanging_indent > 0 {
+                    spans.insert(0, Span::raw(" ".repeat(hanging_indent)));
+                }
+                row_start += line.line().graphemes(true).count();
+                if !matches!(line.type_of_line(), TypeOfLine::Parent | TypeOfLine::Child) {
+                    row_start += 1;
+                }
+                Line::from(scroll_spans(spans, scroll_column))
+            })
+            .collect()
     }
 
     pub fn ending_of_gap(&self) -> usize {
@@ -1131,4 +1277,89 @@ impl LinesGapBuffer {
     pub fn buffer(&self) -> &[TextEditorLine] {
         &self.buffer
     }
+
+    pub fn window_width(&self) -> usize {
+        self.wrap.width
+    }
+
+    pub fn tab_width(&self) -> usize {
+        self.wrap.tab_width
+    }
+
+    pub fn wrap(&self) -> WrapOptions {
+        self.wrap
+    }
+}
+
+// drops the columns of a row scrolled off the left of the window, blanking what is left of a
+// wide grapheme cut in half
+fn scroll_spans(spans: Vec<Span<'_>>, columns: usize) -> Vec<Span<'_>> {
+    let mut skipped = 0;
+    spans
+        .into_iter()
+        .filter_map(|span| {
+            if skipped >= columns {
+                return Some(span);
+            }
+            let mut kept = String::new();
+            for grapheme in span.content.graphemes(true) {
+                if skipped >= columns {
+                    kept.push_str(grapheme);
+                    continue;
+                }
+                skipped += grapheme.width();
+                if skipped > columns {
+                    kept.push_str(&" ".repeat(skipped - columns));
+                }
+            }
+            (!kept.is_empty()).then(|| Span::styled(kept, span.style))
+        })
+        .collect()
+}
+
+// splits a row into spans so that graphemes covered by a highlight take its style, with later
+// highlights taking precedence over earlier ones, and tabs drawn as spaces up to the next tab stop
+fn style_line<'a>(
+    line: &'a str,
+    row_start: usize,
+    highlights: &[(Range<usize>, Style)],
+    tab_width: usize,
+) -> Vec<Span<'a>> {
+    if highlights.is_empty() && !line.contains('\t') {
+        return vec![Span::raw(line)];
+    }
+    let mut spans = Vec::new();
+    let mut span_start = 0;
+    let mut span_style = None;
+    let mut column = 0;
+    for (offset, (byte_index, grapheme)) in line.grapheme_indices(true).enumerate() {
+        let rope_index = row_start + offset;
+        let style = highlights
+            .iter()
+            .rev()
+            .find(|(range, _)| range.contains(&rope_index))
+            .map(|(_, style)| *style);
+        let is_tab = grapheme == "\t";
+        if byte_index > span_start && (style != span_style || is_tab) {
+            spans.push(Span::styled(
+                &line[span_start..byte_index],
+                span_style.unwrap_or_default(),
+            ));
+            span_start = byte_index;
+        }
+        span_style = style;
+        let width = grapheme_width(grapheme, column, tab_width);
+        if is_tab {
+            spans.push(Span::styled(" ".repeat(width), style.unwrap_or_default()));
+            span_start = byte_index + grapheme.len();
+        }
+        column += width;
+    }
+    if span_start < line.len() || spans.is_empty() {
+        spans.push(Span::styled(
+            &line[span_start..],
+            span_style.unwrap_or_default(),
+        ));
+    }
+    spans
 }
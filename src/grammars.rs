@@ -0,0 +1,486 @@
+use crate::highlight::{
+    Grammar, LineState, Scanner, Token, TokenKind, is_digit, is_identifier_continue,
+    is_identifier_start, is_whitespace,
+};
+
+// continues a possibly nested block comment until it closes or the line runs out
+fn scan_block_comment(scanner: &mut Scanner, depth: usize) -> LineState {
+    let mut depth = depth;
+    while !scanner.is_done() {
+        if scanner.starts_with("*/") {
+            scanner.advance(2);
+            depth -= 1;
+            if depth == 0 {
+                return LineState::Normal;
+            }
+        } else if scanner.starts_with("/*") {
+            scanner.advance(2);
+            depth += 1;
+        } else {
+            scanner.advance(1);
+        }
+    }
+    LineState::BlockComment(depth)
+}
+
+// continues a string until its closing delimiter, reporting whether it was found on this line
+fn scan_string(scanner: &mut Scanner, delimiter: &str, escapes: bool) -> bool {
+    while let Some(grapheme) = scanner.peek() {
+        if escapes && grapheme == "\\" {
+            scanner.advance(2);
+        } else if scanner.starts_with(delimiter) {
+            scanner.advance(delimiter.len());
+            return true;
+        } else {
+            scanner.advance(1);
+        }
+    }
+    false
+}
+
+fn scan_raw_string(scanner: &mut Scanner, hashes: usize) -> bool {
+    let closing = format!("\"{}", "#".repeat(hashes));
+    scan_string(scanner, &closing, false)
+}
+
+fn scan_number(scanner: &mut Scanner) {
+    while let Some(grapheme) = scanner.peek() {
+        let is_fraction = grapheme == "." && scanner.peek_at(1).is_some_and(is_digit);
+        if !is_identifier_continue(grapheme) && !is_fraction {
+            break;
+        }
+        scanner.advance(1);
+    }
+}
+
+const RUST_KEYWORDS: &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern",
+    "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref",
+    "return", "self", "Self", "static", "struct", "super", "trait", "type", "unsafe", "use",
+    "where", "while", "yield",
+];
+const RUST_PRIMITIVES: &[&str] = &[
+    "bool", "char", "str", "u8", "u16", "u32", "u64", "u128", "usize", "i8", "i16", "i32", "i64",
+    "i128", "isize", "f32", "f64",
+];
+
+pub struct RustGrammar;
+impl RustGrammar {
+    fn continue_state(
+        scanner: &mut Scanner,
+        state: LineState,
+        tokens: &mut Vec<Token>,
+    ) -> LineState {
+        let (state, kind) = match state {
+            LineState::BlockComment(depth) => {
+                (scan_block_comment(scanner, depth), TokenKind::Comment)
+            }
+            LineState::String => match scan_string(scanner, "\"", true) {
+                true => (LineState::Normal, TokenKind::String),
+                false => (LineState::String, TokenKind::String),
+            },
+            LineState::RawString(hashes) => match scan_raw_string(scanner, hashes) {
+                true => (LineState::Normal, TokenKind::String),
+                false => (LineState::RawString(hashes), TokenKind::String),
+            },
+            _ => return LineState::Normal,
+        };
+        tokens.push(Token::new(0..scanner.position(), kind));
+        state
+    }
+    // the number of hashes of a raw string starting here, skipping its b prefix if there is one
+    fn raw_string_hashes(scanner: &Scanner) -> Option<(usize, usize)> {
+        let prefix = usize::from(scanner.peek() == Some("b"));
+        if scanner.peek_at(prefix) != Some("r") {
+            return None;
+        }
+        let mut hashes = 0;
+        while scanner.peek_at(prefix + 1 + hashes) == Some("#") {
+            hashes += 1;
+        }
+        (scanner.peek_at(prefix + 1 + hashes) == Some("\""))
+            .then_some((prefix + 2 + hashes, hashes))
+    }
+    fn identifier_kind(scanner: &mut Scanner, word: &str) -> Option<TokenKind> {
+        if scanner.peek() == Some("!") && scanner.peek_at(1) != Some("=") {
+            scanner.advance(1);
+            return Some(TokenKind::Macro);
+        }
+        if RUST_KEYWORDS.contains(&word) {
+            return Some(TokenKind::Keyword);
+        }
+        if RUST_PRIMITIVES.contains(&word) {
+            return Some(TokenKind::Type);
+        }
+        if word == "true" || word == "false" {
+            return Some(TokenKind::Constant);
+        }
+        if word.chars().next().is_some_and(char::is_uppercase) {
+            if word.len() > 1 && word.chars().all(|value| !value.is_lowercase()) {
+                return Some(TokenKind::Constant);
+            }
+            return Some(TokenKind::Type);
+        }
+        if scanner.peek_past_whitespace() == Some("(") {
+            return Some(TokenKind::Function);
+        }
+        None
+    }
+}
+impl Grammar for RustGrammar {
+    fn name(&self) -> &'static str {
+        "Rust"
+    }
+    // a quote is as likely to start a lifetime as a character
+    fn auto_pairs(&self) -> &'static [(char, char)] {
+        &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"')]
+    }
+    fn highlight_line(&self, line: &str, state: LineState) -> (Vec<Token>, LineState) {
+        let mut scanner = Scanner::new(line);
+        let mut tokens = Vec::new();
+        let mut state = Self::continue_state(&mut scanner, state, &mut tokens);
+        while state == LineState::Normal {
+            let Some(grapheme) = scanner.peek() else {
+                break;
+            };
+            let start = scanner.position();
+            if scanner.starts_with("//") {
+                scanner.skip_to_end();
+                tokens.push(Token::new(start..scanner.position(), TokenKind::Comment));
+            } else if scanner.starts_with("/*") {
+                scanner.advance(2);
+                state = scan_block_comment(&mut scanner, 1);
+                tokens.push(Token::new(start..scanner.position(), TokenKind::Comment));
+            } else if let Some((prefix_length, hashes)) = Self::raw_string_hashes(&scanner) {
+                scanner.advance(prefix_length);
+                if !scan_raw_string(&mut scanner, hashes) {
+                    state = LineState::RawString(hashes);
+                }
+                tokens.push(Token::new(start..scanner.position(), TokenKind::String));
+            } else if grapheme == "\""
+                || (matches!(grapheme, "b" | "c") && scanner.peek_at(1) == Some("\""))
+            {
+                scanner.advance(if grapheme == "\"" { 1 } else { 2 });
+                if !scan_string(&mut scanner, "\"", true) {
+                    state = LineState::String;
+                }
+                tokens.push(Token::new(start..scanner.position(), TokenKind::String));
+            } else if grapheme == "'" {
+                if scanner.peek_at(1) == Some("\\") {
+                    scanner.advance(2);
+                    scan_string(&mut scanner, "'", true);
+                    tokens.push(Token::new(start..scanner.position(), TokenKind::String));
+                } else if scanner.peek_at(2) == Some("'") {
+                    scanner.advance(3);
+                    tokens.push(Token::new(start..scanner.position(), TokenKind::String));
+                } else {
+                    scanner.advance(1);
+                    scanner.skip_while(is_identifier_continue);
+                    tokens.push(Token::new(start..scanner.position(), TokenKind::Lifetime));
+                }
+            } else if grapheme == "#"
+                && (scanner.peek_at(1) == Some("[")
+                    || (scanner.peek_at(1) == Some("!") && scanner.peek_at(2) == Some("[")))
+            {
+                let mut depth = 0;
+                while let Some(grapheme) = scanner.peek() {
+                    scanner.advance(1);
+                    match grapheme {
+                        "[" => depth += 1,
+                        "]" => {
+                            depth -= 1;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+                tokens.push(Token::new(start..scanner.position(), TokenKind::Attribute));
+            } else if is_digit(grapheme) {
+                scan_number(&mut scanner);
+                tokens.push(Token::new(start..scanner.position(), TokenKind::Number));
+            } else if is_identifier_start(grapheme) {
+                scanner.skip_while(is_identifier_continue);
+                let word = scanner.text(start..scanner.position());
+                if let Some(kind) = Self::identifier_kind(&mut scanner, &word) {
+                    tokens.push(Token::new(start..scanner.position(), kind));
+                }
+            } else {
+                scanner.advance(1);
+            }
+        }
+        (tokens, state)
+    }
+}
+
+pub struct TomlGrammar;
+impl TomlGrammar {
+    fn is_bare_key(grapheme: &str) -> bool {
+        is_identifier_continue(grapheme) || grapheme == "-"
+    }
+    fn is_key(scanner: &Scanner) -> bool {
+        matches!(scanner.peek_past_whitespace(), Some("=" | "."))
+    }
+}
+impl Grammar for TomlGrammar {
+    fn name(&self) -> &'static str {
+        "TOML"
+    }
+    fn highlight_line(&self, line: &str, state: LineState) -> (Vec<Token>, LineState) {
+        let mut scanner = Scanner::new(line);
+        let mut tokens = Vec::new();
+        let mut state = state;
+        if let LineState::TripleString(delimiter) = state {
+            let closed = match delimiter {
+                '"' => scan_string(&mut scanner, "\"\"\"", true),
+                _ => scan_string(&mut scanner, "'''", false),
+            };
+            if closed {
+                state = LineState::Normal;
+            }
+            tokens.push(Token::new(0..scanner.position(), TokenKind::String));
+        } else {
+            state = LineState::Normal;
+        }
+        scanner.skip_while(is_whitespace);
+        if state == LineState::Normal && scanner.peek() == Some("[") {
+            let start = scanner.position();
+            match scanner.find("]", start) {
+                Some(end) => scanner.set_position(end + 1),
+                None => scanner.skip_to_end(),
+            }
+            scanner.skip_while(|grapheme| grapheme == "]");
+            tokens.push(Token::new(start..scanner.position(), TokenKind::Heading));
+        }
+        while state == LineState::Normal {
+            let Some(grapheme) = scanner.peek() else {
+                break;
+            };
+            let start = scanner.position();
+            if grapheme == "#" {
+                scanner.skip_to_end();
+                tokens.push(Token::new(start..scanner.position(), TokenKind::Comment));
+            } else if scanner.starts_with("\"\"\"") || scanner.starts_with("'''") {
+                let delimiter = if grapheme == "\"" { '"' } else { '\'' };
+                scanner.advance(3);
+                let closed = match delimiter {
+                    '"' => scan_string(&mut scanner, "\"\"\"", true),
+                    _ => scan_string(&mut scanner, "'''", false),
+                };
+                if !closed {
+                    state = LineState::TripleString(delimiter);
+                }
+                tokens.push(Token::new(start..scanner.position(), TokenKind::String));
+            } else if grapheme == "\"" || grapheme == "'" {
+                scanner.advance(1);
+                scan_string(&mut scanner, grapheme, grapheme == "\"");
+                let kind = match Self::is_key(&scanner) {
+                    true => TokenKind::Key,
+                    false => TokenKind::String,
+                };
+                tokens.push(Token::new(start..scanner.position(), kind));
+            } else if Self::is_bare_key(grapheme) || grapheme == "+" {
+                scanner.advance(1);
+                scanner.skip_while(Self::is_bare_key);
+                let kind = if Self::is_key(&scanner) {
+                    Some(TokenKind::Key)
+                } else {
+                    let word = scanner.text(start..scanner.position());
+                    match word.as_str() {
+                        "true" | "false" => Some(TokenKind::Constant),
+                        "inf" | "+inf" | "-inf" | "nan" | "+nan" | "-nan" => {
+                            Some(TokenKind::Number)
+                        }
+                        _ if is_digit(&word) || word.starts_with(['+', '-']) => {
+                            // dates and times run on with colons, dots and offsets
+                            scanner.skip_while(|grapheme| {
+                                Self::is_bare_key(grapheme) || matches!(grapheme, ":" | "." | "+")
+                            });
+                            Some(TokenKind::Number)
+                        }
+                        _ => None,
+                    }
+                };
+                if let Some(kind) = kind {
+                    tokens.push(Token::new(start..scanner.position(), kind));
+                }
+            } else {
+                scanner.advance(1);
+            }
+        }
+        (tokens, state)
+    }
+}
+
+pub struct JsonGrammar;
+impl Grammar for JsonGrammar {
+    fn name(&self) -> &'static str {
+        "JSON"
+    }
+    fn highlight_line(&self, line: &str, _state: LineState) -> (Vec<Token>, LineState) {
+        let mut scanner = Scanner::new(line);
+        let mut tokens = Vec::new();
+        while let Some(grapheme) = scanner.peek() {
+            let start = scanner.position();
+            if grapheme == "\"" {
+                scanner.advance(1);
+                scan_string(&mut scanner, "\"", true);
+                let kind = match scanner.peek_past_whitespace() {
+                    Some(":") => TokenKind::Key,
+                    _ => TokenKind::String,
+                };
+                tokens.push(Token::new(start..scanner.position(), kind));
+            } else if is_digit(grapheme) || grapheme == "-" {
+                scanner.advance(1);
+                scanner.skip_while(|grapheme| {
+                    is_digit(grapheme) || matches!(grapheme, "." | "e" | "E" | "+" | "-")
+                });
+                tokens.push(Token::new(start..scanner.position(), TokenKind::Number));
+            } else if is_identifier_start(grapheme) {
+                scanner.skip_while(is_identifier_continue);
+                let word = scanner.text(start..scanner.position());
+                if matches!(word.as_str(), "true" | "false" | "null") {
+                    tokens.push(Token::new(start..scanner.position(), TokenKind::Constant));
+                }
+            } else if scanner.starts_with("//") {
+                scanner.skip_to_end();
+                tokens.push(Token::new(start..scanner.position(), TokenKind::Comment));
+            } else {
+                scanner.advance(1);
+            }
+        }
+        (tokens, LineState::Normal)
+    }
+}
+
+pub struct MarkdownGrammar;
+impl MarkdownGrammar {
+    fn is_fence(scanner: &Scanner) -> bool {
+        scanner.starts_with("```") || scanner.starts_with("~~~")
+    }
+    // emphasis, code spans and links inside a line of text
+    fn highlight_inline(scanner: &mut Scanner, tokens: &mut Vec<Token>) {
+        while let Some(grapheme) = scanner.peek() {
+            let start = scanner.position();
+            if grapheme == "`" {
+                scanner.skip_while(|grapheme| grapheme == "`");
+                let fence = "`".repeat(scanner.position() - start);
+                if let Some(end) = scanner.find(&fence, scanner.position()) {
+                    scanner.set_position(end + fence.len());
+                    tokens.push(Token::new(start..scanner.position(), TokenKind::Code));
+                }
+            } else if scanner.starts_with("**") || scanner.starts_with("__") {
+                let delimiter = if grapheme == "*" { "**" } else { "__" };
+                match scanner.find(delimiter, start + 2) {
+                    Some(end) if end > start + 2 => {
+                        scanner.set_position(end + 2);
+                        tokens.push(Token::new(start..scanner.position(), TokenKind::Strong));
+                    }
+                    _ => scanner.advance(2),
+                }
+            } else if (grapheme == "*" || grapheme == "_")
+                && scanner.peek_at(1).is_some_and(|next| !is_whitespace(next))
+            {
+                match scanner.find(grapheme, start + 1) {
+                    Some(end) if end > start + 1 => {
+                        scanner.set_position(end + 1);
+                        tokens.push(Token::new(start..scanner.position(), TokenKind::Emphasis));
+                    }
+                    _ => scanner.advance(1),
+                }
+            } else if grapheme == "[" {
+                let link_end = scanner
+                    .find("](", start + 1)
+                    .and_then(|middle| scanner.find(")", middle + 2));
+                match link_end {
+                    Some(end) => {
+                        scanner.set_position(end + 1);
+                        tokens.push(Token::new(start..scanner.position(), TokenKind::Link));
+                    }
+                    None => scanner.advance(1),
+                }
+            } else {
+                scanner.advance(1);
+            }
+        }
+    }
+}
+impl Grammar for MarkdownGrammar {
+    fn name(&self) -> &'static str {
+        "Markdown"
+    }
+    fn indent_openers(&self) -> &'static [&'static str] {
+        &[]
+    }
+    fn highlight_line(&self, line: &str, state: LineState) -> (Vec<Token>, LineState) {
+        let mut scanner = Scanner::new(line);
+        let mut tokens = Vec::new();
+        let whole_line = 0..scanner.len();
+        scanner.skip_while(is_whitespace);
+        if state == LineState::CodeBlock {
+            let state = match Self::is_fence(&scanner) {
+                true => LineState::Normal,
+                false => LineState::CodeBlock,
+            };
+            tokens.push(Token::new(whole_line, TokenKind::Code));
+            return (tokens, state);
+        }
+        if Self::is_fence(&scanner) {
+            tokens.push(Token::new(whole_line, TokenKind::Code));
+            return (tokens, LineState::CodeBlock);
+        }
+        let start = scanner.position();
+        match scanner.peek() {
+            Some("#") => {
+                scanner.skip_while(|grapheme| grapheme == "#");
+                let level = scanner.position() - start;
+                if level <= 6 && scanner.peek().is_none_or(is_whitespace) {
+                    tokens.push(Token::new(whole_line, TokenKind::Heading));
+                    return (tokens, LineState::Normal);
+                }
+                scanner.set_position(start);
+            }
+            Some(">") => {
+                tokens.push(Token::new(whole_line, TokenKind::Comment));
+                return (tokens, LineState::Normal);
+            }
+            Some("-" | "*" | "+") => {
+                let marker = scanner.peek().unwrap_or_default();
+                let is_rule = scanner.len() - start >= 3
+                    && line
+                        .trim()
+                        .chars()
+                        .all(|value| value == ' ' || marker.starts_with(value));
+                if is_rule {
+                    tokens.push(Token::new(whole_line, TokenKind::Punctuation));
+                    return (tokens, LineState::Normal);
+                }
+                if scanner.peek_at(1).is_some_and(is_whitespace) {
+                    scanner.advance(1);
+                    tokens.push(Token::new(
+                        start..scanner.position(),
+                        TokenKind::Punctuation,
+                    ));
+                }
+            }
+            Some(grapheme) if is_digit(grapheme) => {
+                scanner.skip_while(is_digit);
+                if matches!(scanner.peek(), Some("." | ")"))
+                    && scanner.peek_at(1).is_none_or(is_whitespace)
+                {
+                    scanner.advance(1);
+                    tokens.push(Token::new(
+                        start..scanner.position(),
+                        TokenKind::Punctuation,
+                    ));
+                } else {
+                    scanner.set_position(start);
+                }
+            }
+            _ => {}
+        }
+        Self::highlight_inline(&mut scanner, &mut tokens);
+        (tokens, LineState::Normal)
+    }
+}
@@ -0,0 +1,64 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::text_representation::TextRepresentation;
+
+const CHUNK_LENGTH: usize = 256;
+
+// reads the text around an index a chunk at a time, so that motions and line lookups never collect
+// the whole text
+pub struct GraphemeReader<'a> {
+    text_representation: &'a dyn TextRepresentation,
+    length: usize,
+    chunk_start: usize,
+    chunk: Vec<String>,
+}
+impl<'a> GraphemeReader<'a> {
+    pub fn new(text_representation: &'a dyn TextRepresentation) -> Self {
+        Self {
+            text_representation,
+            length: text_representation.length(),
+            chunk_start: 0,
+            chunk: vec![],
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.length
+    }
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+    pub fn get(&mut self, index: usize) -> Option<&str> {
+        if index >= self.length {
+            return None;
+        }
+        if index < self.chunk_start || index >= self.chunk_start + self.chunk.len() {
+            self.chunk_start = index.saturating_sub(CHUNK_LENGTH / 2);
+            let mut content = String::new();
+            self.text_representation.collect_substring(
+                &mut content,
+                (self.chunk_start, self.chunk_start + CHUNK_LENGTH - 1),
+            );
+            self.chunk = content.graphemes(true).map(str::to_string).collect();
+        }
+        self.chunk.get(index - self.chunk_start).map(String::as_str)
+    }
+    pub fn is_newline(&mut self, index: usize) -> bool {
+        matches!(self.get(index), Some("\n" | "\r\n"))
+    }
+}
+
+pub fn line_start(reader: &mut GraphemeReader, index: usize) -> usize {
+    let mut index = index.min(reader.len());
+    while index > 0 && !reader.is_newline(index - 1) {
+        index -= 1;
+    }
+    index
+}
+// the index of the newline ending the line, or the length of the text on the last line
+pub fn line_end(reader: &mut GraphemeReader, index: usize) -> usize {
+    let mut index = index;
+    while index < reader.len() && !reader.is_newline(index) {
+        index += 1;
+    }
+    index
+}
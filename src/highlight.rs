@@ -0,0 +1,453 @@
+use std::{iter::repeat_n, ops::Range, path::Path};
+
+use ratatui::style::{Color, Modifier, Style};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    brackets::AUTO_PAIRS,
+    gap_buffer::LinesGapBuffer,
+    grammars::{JsonGrammar, MarkdownGrammar, RustGrammar, TomlGrammar},
+    text_editor_line::TypeOfLine,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenKind {
+    Keyword,
+    Type,
+    Function,
+    Macro,
+    String,
+    Number,
+    Constant,
+    Comment,
+    Attribute,
+    Lifetime,
+    Key,
+    Heading,
+    Emphasis,
+    Strong,
+    Code,
+    Link,
+    Punctuation,
+}
+impl TokenKind {
+    pub fn style(&self) -> Style {
+        match self {
+            TokenKind::Keyword => Style::default().fg(Color::Magenta),
+            TokenKind::Type => Style::default().fg(Color::Yellow),
+            TokenKind::Function => Style::default().fg(Color::Blue),
+            TokenKind::Macro => Style::default().fg(Color::LightCyan),
+            TokenKind::String => Style::default().fg(Color::Green),
+            TokenKind::Number => Style::default().fg(Color::LightRed),
+            TokenKind::Constant => Style::default().fg(Color::LightRed),
+            TokenKind::Comment => Style::default()
+                .fg(Color::DarkGray)
+                .add_modifier(Modifier::ITALIC),
+            TokenKind::Attribute => Style::default().fg(Color::Cyan),
+            TokenKind::Lifetime => Style::default().fg(Color::LightMagenta),
+            TokenKind::Key => Style::default().fg(Color::LightBlue),
+            TokenKind::Heading => Style::default()
+                .fg(Color::LightBlue)
+                .add_modifier(Modifier::BOLD),
+            TokenKind::Emphasis => Style::default().add_modifier(Modifier::ITALIC),
+            TokenKind::Strong => Style::default().add_modifier(Modifier::BOLD),
+            TokenKind::Code => Style::default().fg(Color::Green),
+            TokenKind::Link => Style::default()
+                .fg(Color::Cyan)
+                .add_modifier(Modifier::UNDERLINED),
+            TokenKind::Punctuation => Style::default().fg(Color::LightYellow),
+        }
+    }
+    pub fn is_string_or_comment(&self) -> bool {
+        matches!(self, TokenKind::String | TokenKind::Comment)
+    }
+}
+
+// a token covers a range of graphemes within one logical line
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub range: Range<usize>,
+    pub kind: TokenKind,
+}
+impl Token {
+    pub fn new(range: Range<usize>, kind: TokenKind) -> Self {
+        Self { range, kind }
+    }
+}
+
+// what is still open at the end of a logical line and carries over into the next one
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LineState {
+    #[default]
+    Normal,
+    BlockComment(usize),
+    String,
+    RawString(usize),
+    TripleString(char),
+    CodeBlock,
+}
+
+pub trait Grammar {
+    fn name(&self) -> &'static str;
+    fn highlight_line(&self, line: &str, state: LineState) -> (Vec<Token>, LineState);
+    // what a line can end with for Enter to indent the next one a level deeper
+    fn indent_openers(&self) -> &'static [&'static str] {
+        &["{", "(", "["]
+    }
+    fn auto_pairs(&self) -> &'static [(char, char)] {
+        AUTO_PAIRS
+    }
+}
+
+pub fn grammar_for_path(file_path: &str) -> Option<Box<dyn Grammar>> {
+    let extension = Path::new(file_path).extension()?.to_str()?;
+    match extension.to_ascii_lowercase().as_str() {
+        "rs" => Some(Box::new(RustGrammar)),
+        "toml" => Some(Box::new(TomlGrammar)),
+        "md" | "markdown" => Some(Box::new(MarkdownGrammar)),
+        "json" => Some(Box::new(JsonGrammar)),
+        _ => None,
+    }
+}
+
+// lets the grammars walk a logical line grapheme by grapheme
+pub struct Scanner<'a> {
+    graphemes: Vec<&'a str>,
+    position: usize,
+}
+impl<'a> Scanner<'a> {
+    pub fn new(line: &'a str) -> Self {
+        Self {
+            graphemes: line.graphemes(true).collect(),
+            position: 0,
+        }
+    }
+    pub fn position(&self) -> usize {
+        self.position
+    }
+    pub fn len(&self) -> usize {
+        self.graphemes.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.graphemes.is_empty()
+    }
+    pub fn is_done(&self) -> bool {
+        self.position >= self.graphemes.len()
+    }
+    pub fn peek(&self) -> Option<&'a str> {
+        self.peek_at(0)
+    }
+    pub fn peek_at(&self, offset: usize) -> Option<&'a str> {
+        self.graphemes.get(self.position + offset).copied()
+    }
+    pub fn starts_with(&self, pattern: &str) -> bool {
+        self.matches_at(self.position, pattern)
+    }
+    fn matches_at(&self, position: usize, pattern: &str) -> bool {
+        pattern
+            .graphemes(true)
+            .enumerate()
+            .all(|(offset, grapheme)| self.graphemes.get(position + offset) == Some(&grapheme))
+    }
+    // the position of the next occurrence of the pattern at or after `from`
+    pub fn find(&self, pattern: &str, from: usize) -> Option<usize> {
+        (from..self.graphemes.len()).find(|position| self.matches_at(*position, pattern))
+    }
+    pub fn advance(&mut self, count: usize) {
+        self.position = (self.position + count).min(self.graphemes.len());
+    }
+    pub fn set_position(&mut self, position: usize) {
+        self.position = position.min(self.graphemes.len());
+    }
+    pub fn skip_while(&mut self, predicate: impl Fn(&str) -> bool) {
+        while let Some(grapheme) = self.peek() {
+            if !predicate(grapheme) {
+                break;
+            }
+            self.position += 1;
+        }
+    }
+    pub fn skip_to_end(&mut self) {
+        self.position = self.graphemes.len();
+    }
+    // the next grapheme that is not whitespace, without moving
+    pub fn peek_past_whitespace(&self) -> Option<&'a str> {
+        self.graphemes[self.position..]
+            .iter()
+            .find(|grapheme| !is_whitespace(grapheme))
+            .copied()
+    }
+    pub fn text(&self, range: Range<usize>) -> String {
+        self.graphemes[range].concat()
+    }
+}
+
+pub fn is_whitespace(grapheme: &str) -> bool {
+    grapheme.chars().all(char::is_whitespace)
+}
+pub fn is_digit(grapheme: &str) -> bool {
+    grapheme
+        .chars()
+        .next()
+        .is_some_and(|value| value.is_ascii_digit())
+}
+pub fn is_identifier_start(grapheme: &str) -> bool {
+    grapheme
+        .chars()
+        .next()
+        .is_some_and(|value| value.is_alphabetic() || value == '_')
+}
+pub fn is_identifier_continue(grapheme: &str) -> bool {
+    grapheme
+        .chars()
+        .next()
+        .is_some_and(|value| value.is_alphanumeric() || value == '_')
+}
+
+// which logical lines an edit replaced, so that only those are highlighted again
+#[derive(Clone, Copy, Debug)]
+pub enum LineSplice {
+    Lines {
+        line: usize,
+        removed: usize,
+        inserted: usize,
+    },
+    All,
+}
+
+#[derive(Clone, Default)]
+struct HighlightedLine {
+    tokens: Vec<Token>,
+    start_state: LineState,
+    end_state: LineState,
+    stale: bool,
+}
+impl HighlightedLine {
+    fn stale() -> Self {
+        Self {
+            stale: true,
+            ..Default::default()
+        }
+    }
+}
+
+// keeps the tokens of every logical line, highlighting lines again only once an edit has made them
+// stale or the state carried in from the line above has changed
+pub struct Highlighter {
+    grammar: Box<dyn Grammar>,
+    lines: Vec<HighlightedLine>,
+    first_stale: usize,
+}
+impl Highlighter {
+    pub fn new(grammar: Box<dyn Grammar>) -> Self {
+        Self {
+            grammar,
+            lines: vec![],
+            first_stale: 0,
+        }
+    }
+    pub fn language(&self) -> &'static str {
+        self.grammar.name()
+    }
+    pub fn indent_openers(&self) -> &'static [&'static str] {
+        self.grammar.indent_openers()
+    }
+    pub fn auto_pairs(&self) -> &'static [(char, char)] {
+        self.grammar.auto_pairs()
+    }
+    pub fn splice(&mut self, splice: LineSplice) {
+        match splice {
+            LineSplice::Lines {
+                line,
+                removed,
+                inserted,
+            } => {
+                let line = line.min(self.lines.len());
+                let removed = removed.min(self.lines.len() - line);
+                self.lines.splice(
+                    line..line + removed,
+                    repeat_n(HighlightedLine::stale(), inserted),
+                );
+                self.first_stale = self.first_stale.min(line);
+            }
+            LineSplice::All => {
+                self.lines.clear();
+                self.first_stale = 0;
+            }
+        }
+    }
+    // brings every line up to and including the one holding `up_to_row` up to date
+    pub fn refresh(&mut self, text_editor_lines: &LinesGapBuffer, up_to_row: usize) {
+        let row_count = text_editor_lines.length();
+        let line_count = text_editor_lines.logical_line_number(row_count);
+        if self.lines.len() != line_count {
+            self.lines = vec![HighlightedLine::stale(); line_count];
+            self.first_stale = 0;
+        }
+        if row_count == 0 {
+            return;
+        }
+        let last_line = text_editor_lines.logical_line_of(up_to_row.min(row_count - 1));
+        if self.first_stale > last_line {
+            return;
+        }
+        let mut state = match self.first_stale {
+            0 => LineState::Normal,
+            line => self.lines[line - 1].end_state,
+        };
+        let mut line = 0;
+        let mut text = String::new();
+        for row in 0..row_count {
+            let Some(row_line) = text_editor_lines.line_at(row) else {
+                break;
+            };
+            if row > 0
+                && matches!(
+                    row_line.type_of_line(),
+                    TypeOfLine::Parent | TypeOfLine::Independent
+                )
+            {
+                if line >= self.first_stale {
+                    state = self.highlight_line(line, &text, state);
+                }
+                line += 1;
+                text.clear();
+                if line > last_line {
+                    break;
+                }
+            }
+            if line >= self.first_stale {
+                text.push_str(row_line.line());
+            }
+        }
+        if line <= last_line && line < self.lines.len() {
+            state = self.highlight_line(line, &text, state);
+        }
+        let next_line = last_line + 1;
+        self.first_stale = match self.lines.get(next_line) {
+            Some(next) if next.stale || next.start_state != state => next_line,
+            _ => self
+                .lines
+                .iter()
+                .skip(next_line)
+                .position(|line| line.stale)
+                .map_or(self.lines.len(), |position| next_line + position),
+        };
+    }
+    fn highlight_line(&mut self, line: usize, text: &str, state: LineState) -> LineState {
+        let highlighted_line = &mut self.lines[line];
+        if highlighted_line.stale || highlighted_line.start_state != state {
+            let (tokens, end_state) = self.grammar.highlight_line(text, state);
+            *highlighted_line = HighlightedLine {
+                tokens,
+                start_state: state,
+                end_state,
+                stale: false,
+            };
+        }
+        highlighted_line.end_state
+    }
+    pub fn tokens(&self, line: usize) -> Option<&[Token]> {
+        self.lines
+            .get(line)
+            .filter(|highlighted_line| !highlighted_line.stale)
+            .map(|highlighted_line| highlighted_line.tokens.as_slice())
+    }
+    // the rope indices of the strings and comments highlighted so far on the lines that overlap
+    // the window of rope indices, in order
+    pub fn string_and_comment_ranges(
+        &self,
+        text_editor_lines: &LinesGapBuffer,
+        window: Range<usize>,
+    ) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        let (first_row, _) = text_editor_lines.find_where_rope_index_fits(window.start);
+        let mut line = text_editor_lines.logical_line_of(first_row);
+        let Some(first_row) = text_editor_lines.first_row_of_logical_line(line) else {
+            return ranges;
+        };
+        let mut position =
+            text_editor_lines.length_up_to_non_inclusive_index_for_current_page(first_row, 0, 0);
+        for row in first_row..text_editor_lines.length() {
+            let Some(row_line) = text_editor_lines.line_at(row) else {
+                break;
+            };
+            if position >= window.end {
+                break;
+            }
+            let starts_line = matches!(
+                row_line.type_of_line(),
+                TypeOfLine::Parent | TypeOfLine::Independent
+            );
+            if starts_line && let Some(tokens) = self.tokens(line) {
+                ranges.extend(
+                    tokens
+                        .iter()
+                        .filter(|token| token.kind.is_string_or_comment())
+                        .map(|token| position + token.range.start..position + token.range.end),
+                );
+            }
+            position += row_line.get_line_length_for_offset();
+            if !matches!(
+                row_line.type_of_line(),
+                TypeOfLine::Parent | TypeOfLine::Child
+            ) {
+                line += 1;
+            }
+        }
+        ranges
+    }
+    // turns the tokens of the lines on the page into ranges of rope indices
+    pub fn highlights_for_page(
+        &self,
+        text_editor_lines: &LinesGapBuffer,
+        page_start: usize,
+        page_height: usize,
+        page_index: usize,
+    ) -> Vec<(Range<usize>, Style)> {
+        let row_count = text_editor_lines.length();
+        let page_end = (page_start + page_height).min(row_count);
+        let mut row = page_start;
+        let mut line_start = page_index;
+        while row > 0
+            && matches!(
+                text_editor_lines.get_line_type(row),
+                Some(TypeOfLine::Child | TypeOfLine::Terminator)
+            )
+        {
+            row -= 1;
+            line_start -= text_editor_lines
+                .line_at(row)
+                .map_or(0, |row_line| row_line.line().graphemes(true).count());
+        }
+        let mut line = text_editor_lines.logical_line_of(row);
+        let mut highlights = Vec::new();
+        while row < page_end {
+            if let Some(tokens) = self.tokens(line) {
+                highlights.extend(tokens.iter().map(|token| {
+                    (
+                        line_start + token.range.start..line_start + token.range.end,
+                        token.kind.style(),
+                    )
+                }));
+            }
+            loop {
+                line_start += text_editor_lines
+                    .line_at(row)
+                    .map_or(0, |row_line| row_line.line().graphemes(true).count());
+                row += 1;
+                if row >= row_count
+                    || matches!(
+                        text_editor_lines.get_line_type(row),
+                        Some(TypeOfLine::Parent | TypeOfLine::Independent)
+                    )
+                {
+                    break;
+                }
+            }
+            line_start += 1;
+            line += 1;
+        }
+        highlights
+    }
+}
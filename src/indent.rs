@@ -0,0 +1,81 @@
+pub const DEFAULT_INDENT_WIDTH: usize = 4;
+// wider indentation than this is taken to be alignment rather than a level
+const MAX_DETECTED_WIDTH: usize = 8;
+
+// how one level of indentation is written in a buffer, and how wide hard tabs are drawn
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Indentation {
+    pub hard_tabs: bool,
+    pub width: usize,
+}
+impl Default for Indentation {
+    fn default() -> Self {
+        Self {
+            hard_tabs: false,
+            width: DEFAULT_INDENT_WIDTH,
+        }
+    }
+}
+impl Indentation {
+    // follows whatever most of the indented lines of a file already use
+    pub fn detect(content: &str) -> Self {
+        let mut lines_with_tabs = 0;
+        let mut lines_with_spaces = 0;
+        let mut narrowest = None;
+        for line in content.lines() {
+            if line.starts_with('\t') {
+                lines_with_tabs += 1;
+                continue;
+            }
+            let spaces = line.len() - line.trim_start_matches(' ').len();
+            // a single space is usually the continuation of a block comment
+            if spaces < 2 || line[spaces..].trim().is_empty() {
+                continue;
+            }
+            lines_with_spaces += 1;
+            narrowest = Some(narrowest.map_or(spaces, |narrowest: usize| narrowest.min(spaces)));
+        }
+        Self {
+            hard_tabs: lines_with_tabs > lines_with_spaces,
+            width: narrowest
+                .filter(|width| {
+                    *width <= MAX_DETECTED_WIDTH && lines_with_tabs <= lines_with_spaces
+                })
+                .unwrap_or(DEFAULT_INDENT_WIDTH),
+        }
+    }
+    pub fn unit(&self) -> String {
+        match self.hard_tabs {
+            true => "\t".to_string(),
+            false => " ".repeat(self.width),
+        }
+    }
+    // how many graphemes at the start of a line one level of outdenting takes away
+    pub fn outdent_length(&self, line: &str) -> usize {
+        if line.starts_with('\t') {
+            return 1;
+        }
+        line.chars()
+            .take(self.width)
+            .take_while(|character| *character == ' ')
+            .count()
+    }
+}
+
+// the spaces and tabs a line starts with
+pub fn leading_whitespace(line: &str) -> &str {
+    let end = line
+        .find(|character| character != ' ' && character != '\t')
+        .unwrap_or(line.len());
+    &line[..end]
+}
+
+// the bracket closing an opener, and none for openers such as `:` that nothing closes
+pub fn closer_of(opener: &str) -> Option<char> {
+    match opener {
+        "{" => Some('}'),
+        "(" => Some(')'),
+        "[" => Some(']'),
+        _ => None,
+    }
+}
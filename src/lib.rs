@@ -1,8 +1,21 @@
 pub mod app;
+pub mod brackets;
+pub mod buffer;
 pub mod command;
+pub mod command_line;
 pub mod gap_buffer;
+pub mod grammars;
+pub mod grapheme_reader;
+pub mod highlight;
+pub mod indent;
 pub mod rc_substr;
+pub mod registers;
 pub mod rope;
+pub mod search;
+pub mod status_line;
 pub mod text_editor_line;
 pub mod text_representation;
 pub mod utils;
+pub mod vi;
+pub mod window;
+pub mod words;
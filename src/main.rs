@@ -1,45 +1,30 @@
-use ptree::print_tree;
-use std::{
-    env,
-    fs::File,
-    io::{self, Read},
-};
-use unicode_segmentation::UnicodeSegmentation;
+use std::{env, io};
 
-use ratatui::crossterm::terminal::size;
-use text_editor::{
-    app::App,
-    rope::{Rope, build_rope, collect_string, insert},
+use ratatui::crossterm::{
+    event::{DisableBracketedPaste, EnableBracketedPaste},
+    execute,
+    terminal::size,
 };
+use text_editor::{app::App, registers::Osc52Clipboard, rope::Rope, utils::read_or_create_file};
 fn main() -> io::Result<()> {
-    let file_path = {
-        let mut args = env::args();
-        match args.nth(1) {
-            Some(arg1) => {
-                println!("arg is {}", arg1);
-                arg1
-            }
-            None => {
-                println!("provide the file path or file name");
-                "example.txt".to_string()
-            }
+    // --osc52 sends yanks to the clipboard of the terminal, which also works over ssh
+    let (flags, args): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|arg| arg.starts_with("--"));
+    let use_osc52 = flags.iter().any(|flag| flag == "--osc52");
+    let file_paths = {
+        if args.is_empty() {
+            println!("provide the file path or file name");
+            vec!["example.txt".to_string()]
+        } else {
+            println!("args are {}", args.join(" "));
+            args
         }
     };
-    let mut file = match File::open(&file_path) {
-        Ok(existing_file) => existing_file,
-        Err(err) => match err.kind() {
-            io::ErrorKind::NotFound => File::create_new(&file_path)?,
-            io::ErrorKind::PermissionDenied => {
-                return Err(err);
-            }
-            _ => {
-                return Err(err);
-            }
-        },
-    };
-    let mut contents = String::new();
-    file.read_to_string(&mut contents)?;
-    let text_representation = Rope::new(contents.clone());
+    let mut opened_files = Vec::with_capacity(file_paths.len());
+    for file_path in file_paths {
+        let contents = read_or_create_file(&file_path)?;
+        opened_files.push((file_path, contents));
+    }
     let (initial_window_width, initial_window_height) = match size() {
         Ok(dimensions) => dimensions,
         Err(err) => {
@@ -48,15 +33,24 @@ fn main() -> io::Result<()> {
         }
     };
 
-    ratatui::run(|terminal| {
-        App::new(
-            contents,
-            text_representation,
+    // pasted text then arrives as a single event instead of one key press per character
+    execute!(io::stdout(), EnableBracketedPaste)?;
+    let result = ratatui::run(|terminal| {
+        let mut app = App::new(
             initial_window_width as usize,
             initial_window_height as usize,
-        )
-        .run(terminal)
-    })?;
+        );
+        if use_osc52 {
+            app.set_clipboard(Box::new(Osc52Clipboard::new(io::stdout())));
+        }
+        for (file_path, contents) in opened_files {
+            let text_representation = Rope::new(contents.clone());
+            app.open_buffer(file_path, contents, text_representation);
+        }
+        app.run(terminal)
+    });
+    execute!(io::stdout(), DisableBracketedPaste)?;
+    result?;
 
     // let content: Vec<&str> = contents.graphemes(true).collect::<Vec<&str>>();
     //
@@ -0,0 +1,165 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, Write},
+};
+
+// how many recent deletions are kept in the numbered registers "1 to "9
+pub const KILL_RING_LENGTH: usize = 9;
+// terminals drop OSC 52 sequences much larger than this
+const OSC52_MAX_LENGTH: usize = 100_000;
+
+#[derive(Clone, Default, Debug)]
+pub struct Register {
+    pub text: String,
+    pub linewise: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegisterWrite {
+    Yank,
+    Delete,
+}
+
+// the unnamed register, the last yank in "0, recent deletions in "1 to "9, the letters and the
+// clipboard registers "+ and "*
+#[derive(Default)]
+pub struct Registers {
+    unnamed: Register,
+    last_yank: Register,
+    kill_ring: VecDeque<Register>,
+    named: HashMap<char, Register>,
+}
+impl Registers {
+    pub fn is_valid_name(name: char) -> bool {
+        matches!(name, '"' | '_' | '+' | '*' | '0'..='9') || name.is_ascii_alphabetic()
+    }
+    // yanks into the unnamed register and anything written to "+ or "* reach the clipboard, while
+    // the other registers stay inside the editor
+    pub fn reaches_clipboard(name: Option<char>, write: RegisterWrite) -> bool {
+        match name {
+            None | Some('"') => write == RegisterWrite::Yank,
+            Some(name) => matches!(name, '+' | '*'),
+        }
+    }
+    pub fn get(&self, name: Option<char>) -> Option<&Register> {
+        let register = match name {
+            None | Some('"') => Some(&self.unnamed),
+            Some('0') => Some(&self.last_yank),
+            Some(digit @ '1'..='9') => self
+                .kill_ring
+                .get(digit.to_digit(10).unwrap_or(1) as usize - 1),
+            Some(name) => self.named.get(&name.to_ascii_lowercase()),
+        };
+        register.filter(|register| !register.text.is_empty())
+    }
+    // deletions made without naming a register go onto the kill ring, pushing older ones down
+    pub fn store(&mut self, name: Option<char>, register: Register, write: RegisterWrite) {
+        match name {
+            Some('_') => return,
+            None | Some('"') => match write {
+                RegisterWrite::Yank => self.last_yank = register.clone(),
+                RegisterWrite::Delete => {
+                    self.kill_ring.push_front(register.clone());
+                    self.kill_ring.truncate(KILL_RING_LENGTH);
+                }
+            },
+            // an uppercase name appends to the register of its lowercase letter
+            Some(name) if name.is_ascii_uppercase() => {
+                let named = self.named.entry(name.to_ascii_lowercase()).or_default();
+                named.text.push_str(&register.text);
+                named.linewise |= register.linewise;
+                self.unnamed = named.clone();
+                return;
+            }
+            Some(name) => {
+                self.named.insert(name, register.clone());
+            }
+        }
+        self.unnamed = register;
+    }
+    pub fn kill_ring(&self) -> impl Iterator<Item = &Register> {
+        self.kill_ring.iter()
+    }
+}
+
+// somewhere outside of the editor that copied text is handed to
+pub trait Clipboard {
+    fn copy(&mut self, text: &str) -> io::Result<()>;
+}
+
+// sets the clipboard of the terminal with the OSC 52 escape sequence, which also reaches the host
+// terminal over ssh
+pub struct Osc52Clipboard<W: Write> {
+    writer: W,
+}
+impl<W: Write> Osc52Clipboard<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+impl<W: Write> Clipboard for Osc52Clipboard<W> {
+    fn copy(&mut self, text: &str) -> io::Result<()> {
+        let encoded = encode_base64(text.as_bytes());
+        if encoded.len() > OSC52_MAX_LENGTH {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "text is too large for the terminal clipboard",
+            ));
+        }
+        write!(self.writer, "\x1b]52;c;{}\x07", encoded)?;
+        self.writer.flush()
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+pub fn encode_base64(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (position, byte)| {
+                group | (*byte as u32) << (16 - position * 8)
+            });
+        for position in 0..4 {
+            if position <= chunk.len() {
+                let sextet = (group >> (18 - position * 6)) & 0b11_1111;
+                encoded.push(BASE64_ALPHABET[sextet as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_pads_the_last_group_to_four_characters() {
+        assert_eq!(encode_base64(b""), "");
+        assert_eq!(encode_base64(b"abc"), "YWJj");
+        assert_eq!(encode_base64(b"abcd"), "YWJjZA==");
+        assert_eq!(encode_base64(b"abcde"), "YWJjZGU=");
+        assert_eq!(encode_base64("é\n".as_bytes()), "w6kK");
+    }
+    #[test]
+    fn osc52_copies_write_the_whole_escape_sequence() {
+        let mut written = Vec::new();
+        let mut clipboard = Osc52Clipboard::new(&mut written);
+        clipboard.copy("abcd").unwrap();
+        clipboard.copy("hi").unwrap();
+        assert_eq!(written, b"\x1b]52;c;YWJjZA==\x07\x1b]52;c;aGk=\x07");
+    }
+    #[test]
+    fn osc52_refuses_text_too_large_for_the_terminal() {
+        let mut written = Vec::new();
+        let mut clipboard = Osc52Clipboard::new(&mut written);
+        assert!(clipboard.copy(&"a".repeat(OSC52_MAX_LENGTH)).is_err());
+        assert!(written.is_empty());
+    }
+}
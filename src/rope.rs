@@ -1,17 +1,20 @@
 use std::{
     borrow::Cow,
-    cmp::{max, min},
+    cmp::{Reverse, max, min},
     fs::File,
     io,
     ops::Deref,
 };
 
 use ptree::{Style, TreeBuilder, TreeItem, item::StringItem, write_tree};
+use regex::Regex;
 use unicode_segmentation::UnicodeSegmentation;
 
 use crate::{
-    command::{Command, DeleteCommand, InsertCommand},
+    command::{Command, CompositeCommand, DeleteCommand, InsertCommand, ReplaceCommand},
+    grapheme_reader::{GraphemeReader, line_start},
     rc_substr::RcSubstr,
+    search::{RegexMatch, find_all_in_range, find_backward, find_forward, find_regex_matches},
     text_representation::TextRepresentation,
 };
 #[derive(Default)]
@@ -19,12 +22,19 @@ pub struct Rope {
     rope: Option<Box<Node>>,
     undo_commands: Vec<Box<dyn Command>>,
     redo_commands: Vec<Box<dyn Command>>,
+    saved_undo_depth: Option<usize>,
 }
 impl Rope {
     pub fn new(content: String) -> Self {
         let content: Vec<&str> = content.graphemes(true).collect::<Vec<&str>>();
+        let rope = if content.is_empty() {
+            Box::new(Node::default())
+        } else {
+            build_rope(&content, 0, content.len() - 1).0
+        };
         Self {
-            rope: Some(build_rope(&content, 0, content.len() - 1).0),
+            rope: Some(rope),
+            saved_undo_depth: Some(0),
             ..Default::default()
         }
     }
@@ -35,12 +45,23 @@ impl Rope {
             let (new_rope, new_index) = command.execute(rope);
             self.rope = Some(new_rope);
             final_index = new_index;
-            self.display_structure();
         }
+        if let Some(saved_undo_depth) = self.saved_undo_depth
+            && saved_undo_depth > self.undo_commands.len()
+        {
+            // the saved state only lived in the redo history that is being discarded
+            self.saved_undo_depth = None;
+        }
+        self.redo_commands.clear();
         self.undo_commands.push(Box::new(command));
         final_index
     }
 }
+impl From<String> for Rope {
+    fn from(content: String) -> Self {
+        Rope::new(content)
+    }
+}
 impl TextRepresentation for Rope {
     fn insert(&mut self, content: String, index: usize) -> usize {
         self.execute(InsertCommand::new(content, index))
@@ -50,6 +71,23 @@ impl TextRepresentation for Rope {
         self.execute(DeleteCommand::new(length_to_cut, index))
     }
 
+    fn replace(&mut self, replacements: Vec<RegexMatch>) -> usize {
+        let mut replacements = replacements;
+        // replacing from the end keeps the indices of the earlier matches valid
+        replacements.sort_by_key(|regex_match| Reverse(regex_match.start));
+        let commands = replacements
+            .into_iter()
+            .map(|regex_match| {
+                Box::new(ReplaceCommand::new(
+                    regex_match.start,
+                    regex_match.length,
+                    regex_match.replacement,
+                )) as Box<dyn Command>
+            })
+            .collect();
+        self.execute(CompositeCommand::new(commands))
+    }
+
     fn undo(&mut self) -> Option<usize> {
         if let Some(last_executed_command) = self.undo_commands.pop() {
             let old_rope = self.rope.take();
@@ -82,6 +120,58 @@ impl TextRepresentation for Rope {
         }
     }
 
+    fn mark_saved(&mut self) {
+        self.saved_undo_depth = Some(self.undo_commands.len());
+    }
+
+    fn is_modified(&self) -> bool {
+        self.saved_undo_depth != Some(self.undo_commands.len())
+    }
+
+    fn last_cut(&self) -> Option<String> {
+        self.undo_commands.last()?.cut_content()
+    }
+
+    fn length(&self) -> usize {
+        match self.rope {
+            Some(ref rope) => rope.length,
+            None => 0,
+        }
+    }
+
+    fn find(&self, pattern: &str, from: usize) -> Option<usize> {
+        let rope = self.rope.as_ref()?;
+        find_forward(rope, pattern, from)
+    }
+
+    fn rfind(&self, pattern: &str, before: usize) -> Option<usize> {
+        let rope = self.rope.as_ref()?;
+        find_backward(rope, pattern, before)
+    }
+
+    fn find_regex(
+        &self,
+        regex: &Regex,
+        replacement: &str,
+        bounds: (usize, usize),
+        limit: usize,
+    ) -> Vec<RegexMatch> {
+        match self.rope {
+            Some(ref rope) => {
+                let line_start = line_start(&mut GraphemeReader::new(self), bounds.0);
+                find_regex_matches(rope, regex, replacement, bounds, line_start, limit)
+            }
+            None => vec![],
+        }
+    }
+
+    fn find_all(&self, pattern: &str, bounds: (usize, usize)) -> Vec<usize> {
+        match self.rope {
+            Some(ref rope) => find_all_in_range(rope, pattern, bounds),
+            None => vec![],
+        }
+    }
+
     fn collect_string(&self, text: &mut String) {
         if let Some(ref rope) = self.rope {
             text.clear();
@@ -307,6 +397,69 @@ pub fn build_rope(content: &[&str], starting: usize, ending: usize) -> (Box<Node
         (Box::new(Node::new(str_content, (ending - starting) + 1)), 0)
     }
 }
+pub struct Leaves<'a> {
+    stack: Vec<&'a Node>,
+    first_leaf_offset: usize,
+    next_start: usize,
+}
+impl<'a> Iterator for Leaves<'a> {
+    type Item = (usize, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(node) = self.stack.pop() {
+            match node.str_content {
+                Some(ref content) => {
+                    let offset = self.first_leaf_offset;
+                    self.first_leaf_offset = 0;
+                    if offset >= node.length {
+                        continue;
+                    }
+                    let start = self.next_start;
+                    self.next_start += node.length - offset;
+                    return Some((start, content.get_part_of_string(offset..node.length)));
+                }
+                None => {
+                    if let Some(ref right_node) = node.right {
+                        self.stack.push(right_node);
+                    }
+                    if let Some(ref left_node) = node.left {
+                        self.stack.push(left_node);
+                    }
+                }
+            }
+        }
+        None
+    }
+}
+
+// walks the leaves in order starting with the grapheme at index, without visiting the leaves
+// before it
+pub fn leaves_from(node: &Node, index: usize) -> Leaves<'_> {
+    let mut stack = Vec::new();
+    let mut local_index = index;
+    let mut current_node = Some(node);
+    while let Some(rope_node) = current_node {
+        if rope_node.str_content.is_some() {
+            stack.push(rope_node);
+            break;
+        }
+        if local_index < rope_node.weight {
+            if let Some(ref right_node) = rope_node.right {
+                stack.push(right_node);
+            }
+            current_node = rope_node.left.as_deref();
+        } else {
+            local_index -= rope_node.weight;
+            current_node = rope_node.right.as_deref();
+        }
+    }
+    Leaves {
+        stack,
+        first_leaf_offset: local_index,
+        next_start: index,
+    }
+}
+
 pub fn collect_string(node: &Node, content: &mut String) {
     match node.str_content {
         Some(ref current) => {
@@ -660,6 +813,7 @@ pub fn collect_leaves(node: Box<Node>, leaves: &mut Vec<Box<Node>>) {
     }
 }
 
+#[allow(clippy::needless_range_loop)]
 pub fn rebalance(node: Box<Node>) -> Box<Node> {
     if node.is_balanced() {
         return node;
@@ -0,0 +1,246 @@
+use regex::Regex;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::rope::{Node, leaves_from};
+
+// Knuth-Morris-Pratt over graphemes so that a match can start in one leaf and end in another
+// without collecting the rope into a single string
+struct GraphemeMatcher<'a> {
+    pattern: Vec<&'a str>,
+    failure: Vec<usize>,
+    matched: usize,
+}
+impl<'a> GraphemeMatcher<'a> {
+    fn new(pattern: &'a str) -> Self {
+        let pattern: Vec<&str> = pattern.graphemes(true).collect();
+        let mut failure = vec![0; pattern.len()];
+        let mut matched = 0;
+        for position in 1..pattern.len() {
+            while matched > 0 && pattern[position] != pattern[matched] {
+                matched = failure[matched - 1];
+            }
+            if pattern[position] == pattern[matched] {
+                matched += 1;
+            }
+            failure[position] = matched;
+        }
+        Self {
+            pattern,
+            failure,
+            matched: 0,
+        }
+    }
+    fn len(&self) -> usize {
+        self.pattern.len()
+    }
+    // feeds the next grapheme and reports whether a match ends on it
+    fn push(&mut self, grapheme: &str) -> bool {
+        while self.matched > 0 && grapheme != self.pattern[self.matched] {
+            self.matched = self.failure[self.matched - 1];
+        }
+        if grapheme == self.pattern[self.matched] {
+            self.matched += 1;
+        }
+        if self.matched == self.pattern.len() {
+            self.matched = self.failure[self.matched - 1];
+            true
+        } else {
+            false
+        }
+    }
+}
+
+pub fn find_forward(rope: &Node, pattern: &str, from: usize) -> Option<usize> {
+    let mut matcher = GraphemeMatcher::new(pattern);
+    if matcher.len() == 0 {
+        return None;
+    }
+    for (leaf_start, content) in leaves_from(rope, from) {
+        for (offset, grapheme) in content.graphemes(true).enumerate() {
+            if matcher.push(grapheme) {
+                return Some(leaf_start + offset + 1 - matcher.len());
+            }
+        }
+    }
+    None
+}
+
+pub fn find_backward(rope: &Node, pattern: &str, before: usize) -> Option<usize> {
+    let mut matcher = GraphemeMatcher::new(pattern);
+    if matcher.len() == 0 {
+        return None;
+    }
+    let mut last_match = None;
+    for (leaf_start, content) in leaves_from(rope, 0) {
+        for (offset, grapheme) in content.graphemes(true).enumerate() {
+            if matcher.push(grapheme) {
+                let match_start = leaf_start + offset + 1 - matcher.len();
+                if match_start >= before {
+                    return last_match;
+                }
+                last_match = Some(match_start);
+            }
+        }
+    }
+    last_match
+}
+
+pub fn find_all_in_range(
+    rope: &Node,
+    pattern: &str,
+    (starting, ending): (usize, usize),
+) -> Vec<usize> {
+    let mut matcher = GraphemeMatcher::new(pattern);
+    let mut matches = Vec::new();
+    if matcher.len() == 0 {
+        return matches;
+    }
+    for (leaf_start, content) in leaves_from(rope, starting) {
+        for (offset, grapheme) in content.graphemes(true).enumerate() {
+            let position = leaf_start + offset;
+            if position >= ending + matcher.len() {
+                return matches;
+            }
+            if matcher.push(grapheme) {
+                let match_start = position + 1 - matcher.len();
+                if match_start < ending {
+                    matches.push(match_start);
+                }
+            }
+        }
+    }
+    matches
+}
+
+#[derive(Clone, Debug)]
+pub struct RegexMatch {
+    pub start: usize,
+    pub length: usize,
+    pub replacement: String,
+}
+
+// hands out every logical line with the index of its first grapheme, assembling lines that span
+// several leaves
+struct LinesFromLeaves<'a, I: Iterator<Item = (usize, &'a str)>> {
+    leaves: I,
+    current_leaf: Option<(usize, &'a str)>,
+    line: String,
+    line_start: usize,
+    line_length: usize,
+}
+impl<'a, I: Iterator<Item = (usize, &'a str)>> LinesFromLeaves<'a, I> {
+    fn new(leaves: I, line_start: usize) -> Self {
+        Self {
+            leaves,
+            current_leaf: None,
+            line: String::new(),
+            line_start,
+            line_length: 0,
+        }
+    }
+    fn next_line(&mut self) -> Option<(usize, &str)> {
+        self.line_start += self.line_length;
+        self.line.clear();
+        self.line_length = 0;
+        loop {
+            let (leaf_start, content) = match self.current_leaf.take() {
+                Some(leaf) => leaf,
+                None => match self.leaves.next() {
+                    Some(leaf) => leaf,
+                    None => {
+                        if self.line_length == 0 {
+                            return None;
+                        }
+                        return Some((self.line_start, &self.line));
+                    }
+                },
+            };
+            for (offset, (byte_index, grapheme)) in content.grapheme_indices(true).enumerate() {
+                self.line_length += 1;
+                if grapheme == "\n" || grapheme == "\r\n" {
+                    let rest = &content[byte_index + grapheme.len()..];
+                    if !rest.is_empty() {
+                        self.current_leaf = Some((leaf_start + offset + 1, rest));
+                    }
+                    return Some((self.line_start, &self.line));
+                }
+                self.line.push_str(grapheme);
+            }
+        }
+    }
+}
+
+fn grapheme_offset(line: &str, byte_offset: usize) -> usize {
+    line.grapheme_indices(true)
+        .take_while(|(byte_index, _)| *byte_index < byte_offset)
+        .count()
+}
+
+// runs the regex against one logical line at a time, so matches never span a line break, reading
+// from `line_start`, the start of the line that holds `starting`
+pub fn find_regex_matches(
+    rope: &Node,
+    regex: &Regex,
+    replacement: &str,
+    (starting, ending): (usize, usize),
+    line_start: usize,
+    limit: usize,
+) -> Vec<RegexMatch> {
+    let mut matches = Vec::new();
+    let mut lines = LinesFromLeaves::new(leaves_from(rope, line_start), line_start);
+    while let Some((line_start, line)) = lines.next_line() {
+        if line_start >= ending {
+            break;
+        }
+        for captures in regex.captures_iter(line) {
+            let whole_match = captures.get(0).unwrap();
+            let start = line_start + grapheme_offset(line, whole_match.start());
+            if start < starting {
+                continue;
+            }
+            if start >= ending {
+                break;
+            }
+            let mut expanded = String::new();
+            captures.expand(replacement, &mut expanded);
+            matches.push(RegexMatch {
+                start,
+                length: whole_match.as_str().graphemes(true).count(),
+                replacement: expanded,
+            });
+            if matches.len() == limit {
+                return matches;
+            }
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use crate::{rope::Rope, text_representation::TextRepresentation};
+
+    fn starts(rope: &Rope, pattern: &str, starting: usize) -> Vec<usize> {
+        rope.find_regex(
+            &Regex::new(pattern).unwrap(),
+            "",
+            (starting, usize::MAX),
+            usize::MAX,
+        )
+        .into_iter()
+        .map(|regex_match| regex_match.start)
+        .collect()
+    }
+
+    #[test]
+    fn searching_from_the_middle_of_a_line_reads_it_from_its_start() {
+        let rope = Rope::new("foo bar\r\nfoo baz\nbar".to_string());
+        assert_eq!(starts(&rope, r"\bba\w", 0), vec![4, 12, 16]);
+        assert_eq!(starts(&rope, r"\bba\w", 5), vec![12, 16]);
+        assert_eq!(starts(&rope, r"^foo", 9), Vec::<usize>::new());
+        assert_eq!(starts(&rope, r"^foo", 8), vec![8]);
+        assert_eq!(starts(&rope, r"^bar", 13), vec![16]);
+    }
+}
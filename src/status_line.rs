@@ -0,0 +1,96 @@
+use crate::{buffer::Buffer, text_representation::TextRepresentation};
+
+// the pieces the status line is made up of, in the order `statusline` lists them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatusSegment {
+    Path,
+    Modified,
+    Position,
+    Percentage,
+    Lines,
+    LineEnding,
+    Encoding,
+    Language,
+}
+pub const DEFAULT_SEGMENTS: &[StatusSegment] = &[
+    StatusSegment::Path,
+    StatusSegment::Modified,
+    StatusSegment::Position,
+    StatusSegment::Percentage,
+    StatusSegment::Lines,
+    StatusSegment::LineEnding,
+    StatusSegment::Encoding,
+    StatusSegment::Language,
+];
+
+impl StatusSegment {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StatusSegment::Path => "path",
+            StatusSegment::Modified => "modified",
+            StatusSegment::Position => "position",
+            StatusSegment::Percentage => "percentage",
+            StatusSegment::Lines => "lines",
+            StatusSegment::LineEnding => "lineending",
+            StatusSegment::Encoding => "encoding",
+            StatusSegment::Language => "language",
+        }
+    }
+    pub fn parse(name: &str) -> Option<Self> {
+        DEFAULT_SEGMENTS
+            .iter()
+            .copied()
+            .find(|segment| segment.name() == name)
+    }
+    // what the segment shows for a buffer, which is nothing for the flag of an unmodified one
+    pub fn text<T: TextRepresentation>(&self, buffer: &Buffer<T>) -> String {
+        let line_count = buffer.lines_text_editor().logical_line_count().max(1);
+        match self {
+            StatusSegment::Path => buffer.file_path().to_string(),
+            StatusSegment::Modified => match buffer.is_modified() {
+                true => "[+]".to_string(),
+                false => String::new(),
+            },
+            StatusSegment::Position => format!(
+                "Ln {}, Col {}",
+                buffer.logical_line() + 1,
+                buffer.logical_column() + 1
+            ),
+            StatusSegment::Percentage => {
+                format!("{}%", (buffer.logical_line() + 1) * 100 / line_count)
+            }
+            StatusSegment::Lines => match line_count {
+                1 => "1 line".to_string(),
+                _ => format!("{} lines", line_count),
+            },
+            StatusSegment::LineEnding => buffer.line_ending().to_string(),
+            StatusSegment::Encoding => buffer.encoding().to_string(),
+            StatusSegment::Language => buffer.language().to_string(),
+        }
+    }
+}
+
+// reads a comma separated list of segment names, such as `path,position,language`
+pub fn parse_segments(value: &str) -> Result<Vec<StatusSegment>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            StatusSegment::parse(name).ok_or_else(|| {
+                format!(
+                    "unknown status segment: {}, expected one of {}",
+                    name,
+                    segment_names(DEFAULT_SEGMENTS)
+                )
+            })
+        })
+        .collect()
+}
+pub fn segment_names(segments: &[StatusSegment]) -> String {
+    segments
+        .iter()
+        .map(StatusSegment::name)
+        .collect::<Vec<_>>()
+        .join(",")
+}
@@ -1,14 +1,21 @@
-use crate::text_representation::TextRepresentation;
+use crate::{indent::leading_whitespace, text_representation::TextRepresentation};
 use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
 
 #[derive(Clone, Default, Debug)]
 pub struct TextEditorLine {
     line: String,
+    // the graphemes in the line, which is what a row counts for in rope indices
+    length: usize,
     type_of_line: TypeOfLine,
 }
 impl TextEditorLine {
     pub fn new(line: String, type_of_line: TypeOfLine) -> Self {
-        Self { line, type_of_line }
+        Self {
+            length: line.graphemes(true).count(),
+            line,
+            type_of_line,
+        }
     }
     pub fn change_line(
         &mut self,
@@ -16,19 +23,68 @@ impl TextEditorLine {
         text_representation: &dyn TextRepresentation,
     ) {
         text_representation.collect_substring(&mut self.line, bounds);
+        self.length = self.line.graphemes(true).count();
     }
     pub fn add_to_line(&mut self, new_content: &str) {
         self.line.push_str(new_content);
+        self.length = self.line.graphemes(true).count();
     }
     pub fn get_line_length(&self) -> usize {
-        self.line.len()
+        self.length
+    }
+    // the columns the row takes up on screen
+    pub fn display_width(&self, tab_width: usize) -> usize {
+        display_width(&self.line, tab_width)
+    }
+    // the screen column at which the grapheme at a column of the row is drawn
+    pub fn display_column(&self, column: usize, tab_width: usize) -> usize {
+        self.line
+            .graphemes(true)
+            .take(column)
+            .fold(0, |display_column, grapheme| {
+                display_column + grapheme_width(grapheme, display_column, tab_width)
+            })
+    }
+    // how many graphemes from the start of the row fit in a width, always at least one
+    pub fn graphemes_fitting(&self, width: usize, tab_width: usize) -> usize {
+        let mut display_column = 0;
+        let mut count = 0;
+        for grapheme in self.line.graphemes(true) {
+            display_column += grapheme_width(grapheme, display_column, tab_width);
+            if display_column > width && count > 0 {
+                break;
+            }
+            count += 1;
+        }
+        count
+    }
+    // how many graphemes stay on the row when it wraps at a width, which at word boundaries
+    // is up to the last whitespace that fits, unless the row has no word to break after
+    pub fn wrap_position(&self, width: usize, tab_width: usize, at_words: bool) -> usize {
+        let fitting = self.graphemes_fitting(width, tab_width);
+        if !at_words || fitting >= self.length {
+            return fitting;
+        }
+        let graphemes: Vec<&str> = self.line.graphemes(true).take(fitting).collect();
+        let is_whitespace = |grapheme: &&str| grapheme.chars().all(char::is_whitespace);
+        let Some(first_word) = graphemes
+            .iter()
+            .position(|grapheme| !is_whitespace(grapheme))
+        else {
+            return fitting;
+        };
+        graphemes
+            .iter()
+            .rposition(is_whitespace)
+            .filter(|position| *position > first_word)
+            .map_or(fitting, |position| position + 1)
     }
     pub fn get_line_length_for_offset(&self) -> usize {
         match self.type_of_line {
-            TypeOfLine::Independent => self.line.len() + 1,
-            TypeOfLine::Child => self.line.len(),
-            TypeOfLine::Terminator => self.line.len() + 1,
-            TypeOfLine::Parent => self.line.len(),
+            TypeOfLine::Independent => self.length + 1,
+            TypeOfLine::Child => self.length,
+            TypeOfLine::Terminator => self.length + 1,
+            TypeOfLine::Parent => self.length,
         }
     }
 
@@ -37,6 +93,7 @@ impl TextEditorLine {
     }
     pub fn clear_line(&mut self) {
         self.line.clear();
+        self.length = 0;
     }
     pub fn is_independent(&self) -> bool {
         matches!(self.type_of_line, TypeOfLine::Independent)
@@ -54,6 +111,7 @@ impl TextEditorLine {
         }
 
         self.line = first_part;
+        self.length = self.length.min(cut_position);
         second_part
     }
 
@@ -73,39 +131,113 @@ pub enum TypeOfLine {
     Independent,
     Terminator,
 }
-pub fn generate_lines(content: &str, width: usize) -> (Vec<TextEditorLine>, usize, usize) {
-    let lines: Vec<&str> = content.lines().collect();
+// the cells a grapheme takes up when drawn at a column: two for wide East Asian characters and
+// most emoji, none for zero width characters, and up to the next tab stop for a tab
+pub fn grapheme_width(grapheme: &str, column: usize, tab_width: usize) -> usize {
+    match grapheme {
+        "\t" => tab_width.max(1) - column % tab_width.max(1),
+        _ => grapheme.width(),
+    }
+}
+pub fn display_width(text: &str, tab_width: usize) -> usize {
+    text.graphemes(true).fold(0, |column, grapheme| {
+        column + grapheme_width(grapheme, column, tab_width)
+    })
+}
+// how logical lines are laid out over rows of the window
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub struct WrapOptions {
+    pub width: usize,
+    pub tab_width: usize,
+    // with wrapping off every logical line keeps to a single row, however long
+    pub enabled: bool,
+    // break rows after whitespace rather than in the middle of a word
+    pub at_words: bool,
+    // start the continuation rows of a line under its indentation
+    pub hanging_indent: bool,
+}
+impl WrapOptions {
+    // the columns the continuation rows of a line are pushed in by, leaving at least half the
+    // window for their text
+    pub fn continuation_indent(&self, first_row: &str) -> usize {
+        match self.hanging_indent {
+            true => {
+                display_width(leading_whitespace(first_row), self.tab_width).min(self.width / 2)
+            }
+            false => 0,
+        }
+    }
+}
+// the rows that one logical line takes up once it is wrapped at the width
+pub fn wrap_line(item: &str, wrap: WrapOptions) -> Vec<TextEditorLine> {
+    if !wrap.enabled || display_width(item, wrap.tab_width) <= wrap.width {
+        return vec![TextEditorLine::new(
+            item.to_string(),
+            TypeOfLine::Independent,
+        )];
+    }
+    let continuation_width = wrap.width - wrap.continuation_indent(item);
+    let mut rows = vec![String::new()];
+    let mut column = 0;
+    // the byte in the current row just after its last whitespace, where it can break
+    let mut word_break = None;
+    let mut past_indentation = false;
+    for letter in item.graphemes(true) {
+        let row_width = match rows.len() {
+            1 => wrap.width,
+            _ => continuation_width,
+        };
+        let width = grapheme_width(letter, column, wrap.tab_width);
+        if column > 0 && column + width > row_width {
+            let Some(last_row) = rows.last_mut() else {
+                break;
+            };
+            // a word too long for a row of its own is broken where it reaches the edge
+            let carried = match word_break.take() {
+                Some(position)
+                    if display_width(&last_row[position..], wrap.tab_width) + width
+                        <= continuation_width =>
+                {
+                    last_row.split_off(position)
+                }
+                _ => String::new(),
+            };
+            column = display_width(&carried, wrap.tab_width);
+            rows.push(carried);
+        }
+        column += grapheme_width(letter, column, wrap.tab_width);
+        if let Some(last_row) = rows.last_mut() {
+            last_row.push_str(letter);
+            let is_whitespace = letter.chars().all(char::is_whitespace);
+            past_indentation |= !is_whitespace;
+            if wrap.at_words && is_whitespace && past_indentation {
+                word_break = Some(last_row.len());
+            }
+        }
+    }
+
+    let last_index = rows.len().saturating_sub(1);
+    rows.into_iter()
+        .enumerate()
+        .map(|(index, row)| {
+            let type_of_line = match index {
+                0 => TypeOfLine::Parent,
+                index if index == last_index => TypeOfLine::Terminator,
+                _ => TypeOfLine::Child,
+            };
+            TextEditorLine::new(row, type_of_line)
+        })
+        .collect()
+}
+pub fn generate_lines(content: &str, wrap: WrapOptions) -> (Vec<TextEditorLine>, usize, usize) {
+    let mut lines: Vec<&str> = content.lines().collect();
+    if lines.is_empty() {
+        lines.push("");
+    }
     let lines_count = lines.len();
     let mut my_lines = Vec::with_capacity(lines_count * 3);
     for item in lines {
-        if item.len() <= width {
-            my_lines.push(TextEditorLine {
-                line: item.to_string(),
-                type_of_line: TypeOfLine::Independent,
-            });
-        } else {
-            let graphemes_in_word = item.graphemes(true);
-            let line_len = item.len();
-            let line_count = (line_len / width) + 1;
-            let mut lines_in_string = vec![
-                TextEditorLine {
-                    line: String::new(),
-                    type_of_line: TypeOfLine::Child,
-                };
-                line_count
-            ];
-            for (index, letter) in graphemes_in_word.enumerate() {
-                let index_in_lines_in_string = index / width;
-                lines_in_string[index_in_lines_in_string]
-                    .line
-                    .push_str(letter);
-            }
-
-            let last_index = lines_in_string.len().saturating_sub(1);
-            lines_in_string[0].type_of_line = TypeOfLine::Parent;
-            lines_in_string[last_index].type_of_line = TypeOfLine::Terminator;
-            my_lines.extend(lines_in_string.into_iter());
-        }
+        my_lines.extend(wrap_line(item, wrap));
     }
     let starting_of_gap = my_lines.len();
     let ending_of_gap = starting_of_gap + lines_count.saturating_sub(1);
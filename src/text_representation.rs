@@ -1,8 +1,27 @@
+use regex::Regex;
+
+use crate::search::RegexMatch;
+
 pub trait TextRepresentation {
     fn insert(&mut self, content: String, index: usize) -> usize;
     fn delete(&mut self, length_to_cut: usize, index: usize) -> usize;
+    fn replace(&mut self, replacements: Vec<RegexMatch>) -> usize;
     fn undo(&mut self) -> Option<usize>;
     fn redo(&mut self) -> Option<usize>;
+    fn mark_saved(&mut self);
+    fn is_modified(&self) -> bool;
+    fn last_cut(&self) -> Option<String>;
+    fn length(&self) -> usize;
+    fn find(&self, pattern: &str, from: usize) -> Option<usize>;
+    fn rfind(&self, pattern: &str, before: usize) -> Option<usize>;
+    fn find_all(&self, pattern: &str, bounds: (usize, usize)) -> Vec<usize>;
+    fn find_regex(
+        &self,
+        regex: &Regex,
+        replacement: &str,
+        bounds: (usize, usize),
+        limit: usize,
+    ) -> Vec<RegexMatch>;
     fn collect_string(&self, text: &mut String);
     fn collect_substring(&self, text: &mut String, bounds: (usize, usize));
     fn display_structure(&self);
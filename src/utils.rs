@@ -1 +1,72 @@
-        
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::Path,
+};
+
+pub fn read_or_create_file(file_path: &str) -> io::Result<String> {
+    let mut file = match File::open(file_path) {
+        Ok(existing_file) => existing_file,
+        Err(err) => match err.kind() {
+            io::ErrorKind::NotFound => File::create_new(file_path)?,
+            _ => {
+                return Err(err);
+            }
+        },
+    };
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+// how the lines of a file end, going by the first line that has an ending
+pub fn line_ending_of(contents: &str) -> &'static str {
+    match contents.find(['\n', '\r']) {
+        Some(position) if contents[position..].starts_with("\r\n") => "crlf",
+        Some(position) if contents[position..].starts_with('\r') => "cr",
+        _ => "lf",
+    }
+}
+
+// files are read in as UTF-8, so all that tells them apart is a byte order mark
+pub fn encoding_of(contents: &str) -> &'static str {
+    match contents.starts_with('\u{feff}') {
+        true => "utf-8 bom",
+        false => "utf-8",
+    }
+}
+
+pub fn write_file_atomically(file_path: &str, contents: &str) -> io::Result<()> {
+    let path = Path::new(file_path);
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name.to_string_lossy(),
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a file path", file_path),
+            ));
+        }
+    };
+    let temp_path = path.with_file_name(format!(".{}.{}.tmp", file_name, std::process::id()));
+    let existing_permissions = match fs::metadata(path) {
+        Ok(metadata) => Some(metadata.permissions()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => {
+            return Err(err);
+        }
+    };
+
+    let result = (|| {
+        let mut temp_file = File::create(&temp_path)?;
+        temp_file.write_all(contents.as_bytes())?;
+        temp_file.sync_all()?;
+        if let Some(permissions) = existing_permissions {
+            fs::set_permissions(&temp_path, permissions)?;
+        }
+        fs::rename(&temp_path, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
@@ -0,0 +1,479 @@
+use std::ops::Range;
+
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use crate::{
+    grapheme_reader::{GraphemeReader, line_end, line_start},
+    registers::Registers,
+    text_representation::TextRepresentation,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    LineEnd,
+    FirstLine,
+    LastLine,
+}
+impl Motion {
+    fn is_linewise(&self) -> bool {
+        matches!(
+            self,
+            Motion::Up | Motion::Down | Motion::FirstLine | Motion::LastLine
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ViCommand {
+    Move {
+        motion: Motion,
+        count: Option<usize>,
+    },
+    Operate {
+        operator: Operator,
+        motion: Motion,
+        count: Option<usize>,
+    },
+    OperateOnLines {
+        operator: Operator,
+        count: usize,
+    },
+    DeleteChars {
+        count: usize,
+    },
+    Insert {
+        after: bool,
+    },
+    Put {
+        before: bool,
+        count: usize,
+    },
+    Undo {
+        count: usize,
+    },
+    Redo {
+        count: usize,
+    },
+    Repeat {
+        count: Option<usize>,
+    },
+}
+impl ViCommand {
+    // whether dot-repeat should replay this command
+    pub fn is_change(&self) -> bool {
+        match self {
+            ViCommand::Operate { operator, .. } | ViCommand::OperateOnLines { operator, .. } => {
+                *operator != Operator::Yank
+            }
+            ViCommand::DeleteChars { .. } | ViCommand::Insert { .. } | ViCommand::Put { .. } => {
+                true
+            }
+            _ => false,
+        }
+    }
+    pub fn with_count(self, new_count: usize) -> Self {
+        match self {
+            ViCommand::Operate {
+                operator, motion, ..
+            } => ViCommand::Operate {
+                operator,
+                motion,
+                count: Some(new_count),
+            },
+            ViCommand::OperateOnLines { operator, .. } => ViCommand::OperateOnLines {
+                operator,
+                count: new_count,
+            },
+            ViCommand::DeleteChars { .. } => ViCommand::DeleteChars { count: new_count },
+            ViCommand::Put { before, .. } => ViCommand::Put {
+                before,
+                count: new_count,
+            },
+            command => command,
+        }
+    }
+}
+
+pub enum Parsed {
+    Pending,
+    Command(ViCommand),
+    Invalid,
+    Unhandled,
+}
+
+// collects counts, operators and motions typed in Normal mode until they form a whole command
+#[derive(Default)]
+pub struct CommandParser {
+    count: Option<usize>,
+    operator: Option<(Operator, Option<usize>)>,
+    pending_g: bool,
+    pending_register: bool,
+    register: Option<char>,
+    keys: String,
+}
+impl CommandParser {
+    pub fn is_pending(&self) -> bool {
+        !self.keys.is_empty()
+    }
+    pub fn pending_keys(&self) -> &str {
+        &self.keys
+    }
+    // the register named with `"` for the command being typed
+    pub fn register(&self) -> Option<char> {
+        self.register
+    }
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+    fn total_count(&self) -> Option<usize> {
+        match (self.operator.and_then(|(_, count)| count), self.count) {
+            (None, None) => None,
+            (first, second) => Some(first.unwrap_or(1) * second.unwrap_or(1)),
+        }
+    }
+    fn finish(&mut self, parsed: Parsed) -> Parsed {
+        self.reset();
+        parsed
+    }
+    fn motion(&mut self, motion: Motion) -> Parsed {
+        let count = self.total_count();
+        let command = match self.operator {
+            Some((operator, _)) => ViCommand::Operate {
+                operator,
+                motion,
+                count,
+            },
+            None => ViCommand::Move { motion, count },
+        };
+        self.finish(Parsed::Command(command))
+    }
+    pub fn feed(&mut self, key: KeyEvent) -> Parsed {
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        if self.pending_register {
+            self.pending_register = false;
+            return match key.code {
+                KeyCode::Char(name) if Registers::is_valid_name(name) => {
+                    self.register = Some(name);
+                    self.keys.push(name);
+                    Parsed::Pending
+                }
+                _ => self.finish(Parsed::Invalid),
+            };
+        }
+        if self.pending_g {
+            return match key.code {
+                KeyCode::Char('g') => self.motion(Motion::FirstLine),
+                _ => self.finish(Parsed::Invalid),
+            };
+        }
+        let motion = match key.code {
+            KeyCode::Char('h') | KeyCode::Left if !control => Some(Motion::Left),
+            KeyCode::Char('l') | KeyCode::Right if !control => Some(Motion::Right),
+            KeyCode::Char('k') | KeyCode::Up => Some(Motion::Up),
+            KeyCode::Char('j') | KeyCode::Down => Some(Motion::Down),
+            KeyCode::Char('w') if !control => Some(Motion::WordForward),
+            KeyCode::Char('b') => Some(Motion::WordBackward),
+            KeyCode::Char('e') => Some(Motion::WordEnd),
+            KeyCode::Char('0') if self.count.is_none() => Some(Motion::LineStart),
+            KeyCode::Char('$') => Some(Motion::LineEnd),
+            KeyCode::Char('G') => Some(Motion::LastLine),
+            _ => None,
+        };
+        if let Some(motion) = motion {
+            return self.motion(motion);
+        }
+        if let KeyCode::Char(value) = key.code {
+            if let Some(digit) = value.to_digit(10)
+                && !control
+            {
+                self.count = Some(self.count.unwrap_or(0) * 10 + digit as usize);
+                self.keys.push(value);
+                return Parsed::Pending;
+            }
+            if value == '"' && self.operator.is_none() {
+                self.pending_register = true;
+                self.keys.push(value);
+                return Parsed::Pending;
+            }
+            if value == 'g' {
+                self.pending_g = true;
+                self.keys.push(value);
+                return Parsed::Pending;
+            }
+            let operator = match value {
+                'd' => Some(Operator::Delete),
+                'c' => Some(Operator::Change),
+                'y' if !control => Some(Operator::Yank),
+                _ => None,
+            };
+            if let Some(operator) = operator {
+                return match self.operator {
+                    Some((pending, _)) if pending == operator => {
+                        let count = self.total_count().unwrap_or(1);
+                        self.finish(Parsed::Command(ViCommand::OperateOnLines {
+                            operator,
+                            count,
+                        }))
+                    }
+                    Some(_) => self.finish(Parsed::Invalid),
+                    None => {
+                        self.operator = Some((operator, self.count.take()));
+                        self.keys.push(value);
+                        Parsed::Pending
+                    }
+                };
+            }
+        }
+        if self.operator.is_some() {
+            return self.finish(Parsed::Invalid);
+        }
+        let count = self.count.unwrap_or(1);
+        let command = match key.code {
+            KeyCode::Char('r') if control => ViCommand::Redo { count },
+            KeyCode::Char('x') => ViCommand::DeleteChars { count },
+            KeyCode::Char('i') => ViCommand::Insert { after: false },
+            KeyCode::Char('a') => ViCommand::Insert { after: true },
+            KeyCode::Char('p') => ViCommand::Put {
+                before: false,
+                count,
+            },
+            KeyCode::Char('P') => ViCommand::Put {
+                before: true,
+                count,
+            },
+            KeyCode::Char('u') => ViCommand::Undo { count },
+            KeyCode::Char('.') => ViCommand::Repeat { count: self.count },
+            _ if self.is_pending() => return self.finish(Parsed::Invalid),
+            _ => return Parsed::Unhandled,
+        };
+        self.finish(Parsed::Command(command))
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CharacterClass {
+    Whitespace,
+    Word,
+    Punctuation,
+}
+impl CharacterClass {
+    pub fn of(grapheme: &str) -> Self {
+        match grapheme.chars().next() {
+            Some(value) if value.is_whitespace() => CharacterClass::Whitespace,
+            Some(value) if value.is_alphanumeric() || value == '_' => CharacterClass::Word,
+            Some(_) => CharacterClass::Punctuation,
+            None => CharacterClass::Whitespace,
+        }
+    }
+    fn at(reader: &mut GraphemeReader, index: usize) -> Self {
+        reader
+            .get(index)
+            .map_or(CharacterClass::Whitespace, Self::of)
+    }
+}
+
+pub fn word_forward(reader: &mut GraphemeReader, index: usize) -> usize {
+    let mut index = index;
+    let class = CharacterClass::at(reader, index);
+    if class != CharacterClass::Whitespace {
+        while index < reader.len() && CharacterClass::at(reader, index) == class {
+            index += 1;
+        }
+    }
+    while index < reader.len() && CharacterClass::at(reader, index) == CharacterClass::Whitespace {
+        index += 1;
+    }
+    index
+}
+pub fn word_end(reader: &mut GraphemeReader, index: usize) -> usize {
+    let mut index = index + 1;
+    while index < reader.len() && CharacterClass::at(reader, index) == CharacterClass::Whitespace {
+        index += 1;
+    }
+    if index >= reader.len() {
+        return reader.len().saturating_sub(1);
+    }
+    let class = CharacterClass::at(reader, index);
+    while index + 1 < reader.len() && CharacterClass::at(reader, index + 1) == class {
+        index += 1;
+    }
+    index
+}
+pub fn word_backward(reader: &mut GraphemeReader, index: usize) -> usize {
+    if index == 0 {
+        return 0;
+    }
+    let mut index = index - 1;
+    while index > 0 && CharacterClass::at(reader, index) == CharacterClass::Whitespace {
+        index -= 1;
+    }
+    let class = CharacterClass::at(reader, index);
+    while index > 0 && CharacterClass::at(reader, index - 1) == class {
+        index -= 1;
+    }
+    index
+}
+// the start of the zero based logical line, or of the last line when there are fewer
+pub fn nth_line_start(text_representation: &dyn TextRepresentation, line: usize) -> usize {
+    let mut reader = GraphemeReader::new(text_representation);
+    let mut start = 0;
+    for _ in 0..line {
+        match line_end(&mut reader, start) {
+            newline if newline + 1 < reader.len() => start = newline + 1,
+            _ => break,
+        }
+    }
+    start
+}
+pub fn last_line_start(text_representation: &dyn TextRepresentation) -> usize {
+    let mut reader = GraphemeReader::new(text_representation);
+    let length = reader.len();
+    // a trailing newline does not start another line
+    let end = if length > 0 && reader.is_newline(length - 1) {
+        length - 1
+    } else {
+        length
+    };
+    line_start(&mut reader, end)
+}
+
+pub fn motion_target(
+    text_representation: &dyn TextRepresentation,
+    index: usize,
+    motion: Motion,
+    count: Option<usize>,
+) -> usize {
+    let mut reader = GraphemeReader::new(text_representation);
+    let repeat = count.unwrap_or(1);
+    match motion {
+        Motion::Left => {
+            let start = line_start(&mut reader, index);
+            index.saturating_sub(repeat).max(start)
+        }
+        Motion::Right => {
+            let end = line_end(&mut reader, index);
+            (index + repeat).min(end)
+        }
+        Motion::Up => {
+            let mut start = line_start(&mut reader, index);
+            for _ in 0..repeat {
+                if start == 0 {
+                    break;
+                }
+                start = line_start(&mut reader, start - 1);
+            }
+            start
+        }
+        Motion::Down => {
+            let mut start = line_start(&mut reader, index);
+            for _ in 0..repeat {
+                let end = line_end(&mut reader, start);
+                if end + 1 >= reader.len() {
+                    break;
+                }
+                start = end + 1;
+            }
+            start
+        }
+        Motion::WordForward => (0..repeat).fold(index, |index, _| word_forward(&mut reader, index)),
+        Motion::WordBackward => {
+            (0..repeat).fold(index, |index, _| word_backward(&mut reader, index))
+        }
+        Motion::WordEnd => (0..repeat).fold(index, |index, _| word_end(&mut reader, index)),
+        Motion::LineStart => line_start(&mut reader, index),
+        Motion::LineEnd => line_end(&mut reader, index),
+        Motion::FirstLine => nth_line_start(text_representation, count.unwrap_or(1) - 1),
+        Motion::LastLine => match count {
+            Some(line) => nth_line_start(text_representation, line.saturating_sub(1)),
+            None => last_line_start(text_representation),
+        },
+    }
+}
+
+// whole lines from the one holding `first` to the one holding `last`, including the last newline
+pub fn line_range(
+    text_representation: &dyn TextRepresentation,
+    first: usize,
+    last: usize,
+) -> Range<usize> {
+    let mut reader = GraphemeReader::new(text_representation);
+    let start = line_start(&mut reader, first);
+    let end = line_end(&mut reader, last);
+    start..(end + 1).min(reader.len())
+}
+
+// the text an operator acts on and whether it is made of whole lines
+pub fn operator_range(
+    text_representation: &dyn TextRepresentation,
+    index: usize,
+    operator: Operator,
+    motion: Motion,
+    count: Option<usize>,
+) -> (Range<usize>, bool) {
+    let mut reader = GraphemeReader::new(text_representation);
+    if motion.is_linewise() {
+        let target = motion_target(text_representation, index, motion, count);
+        return (
+            line_range(text_representation, index.min(target), index.max(target)),
+            true,
+        );
+    }
+    let on_word = CharacterClass::at(&mut reader, index) != CharacterClass::Whitespace;
+    let range = match motion {
+        // cw changes to the end of the word like ce, leaving the following whitespace alone
+        Motion::WordForward if operator == Operator::Change && on_word => {
+            index..motion_target(text_representation, index, Motion::WordEnd, count) + 1
+        }
+        // dw on the last word of a line stops at the end of the line
+        Motion::WordForward => {
+            let target = motion_target(text_representation, index, motion, count);
+            let end = line_end(&mut reader, index);
+            match end > index && target > end {
+                true => index..end,
+                false => index..target,
+            }
+        }
+        Motion::WordEnd => index..motion_target(text_representation, index, motion, count) + 1,
+        _ => {
+            let target = motion_target(text_representation, index, motion, count);
+            index.min(target)..index.max(target)
+        }
+    };
+    (range.start..range.end.min(reader.len()), false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rope::Rope;
+
+    #[test]
+    fn line_motions_count_crlf_breaks_as_one_line_break() {
+        for (content, starts) in [("ab\ncd\nef", [0, 3, 6]), ("ab\r\ncd\r\nef", [0, 3, 6])] {
+            let rope = Rope::new(content.to_string());
+            for (line, start) in starts.into_iter().enumerate() {
+                assert_eq!(nth_line_start(&rope, line), start);
+                assert_eq!(
+                    motion_target(&rope, 0, Motion::LastLine, Some(line + 1)),
+                    start
+                );
+            }
+            assert_eq!(nth_line_start(&rope, 10), starts[2]);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+
+use crate::buffer::Viewport;
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct Window {
+    pub buffer: usize,
+    pub viewport: Viewport,
+}
+impl Window {
+    pub fn new(buffer: usize, viewport: Viewport) -> Self {
+        Self { buffer, viewport }
+    }
+}
+
+#[derive(Debug)]
+pub enum Split {
+    Window(usize),
+    Node {
+        direction: Direction,
+        percentage: u16,
+        first: Box<Split>,
+        second: Box<Split>,
+    },
+}
+impl Split {
+    pub fn areas(&self, area: Rect, areas: &mut Vec<(usize, Rect)>) {
+        match self {
+            Split::Window(window) => areas.push((*window, area)),
+            Split::Node {
+                direction,
+                percentage,
+                first,
+                second,
+            } => {
+                let chunks = Layout::default()
+                    .direction(*direction)
+                    .constraints([
+                        Constraint::Percentage(*percentage),
+                        Constraint::Percentage(100 - *percentage),
+                    ])
+                    .split(area);
+                first.areas(chunks[0], areas);
+                second.areas(chunks[1], areas);
+            }
+        }
+    }
+    pub fn split_window(&mut self, window: usize, new_window: usize, direction: Direction) -> bool {
+        match self {
+            Split::Window(current) if *current == window => {
+                *self = Split::Node {
+                    direction,
+                    percentage: 50,
+                    first: Box::new(Split::Window(window)),
+                    second: Box::new(Split::Window(new_window)),
+                };
+                true
+            }
+            Split::Window(_) => false,
+            Split::Node { first, second, .. } => {
+                first.split_window(window, new_window, direction)
+                    || second.split_window(window, new_window, direction)
+            }
+        }
+    }
+    pub fn remove_window(&mut self, window: usize) -> bool {
+        let Split::Node { first, second, .. } = self else {
+            return false;
+        };
+        let remaining = if matches!(first.as_ref(), Split::Window(current) if *current == window) {
+            std::mem::replace(second.as_mut(), Split::Window(0))
+        } else if matches!(second.as_ref(), Split::Window(current) if *current == window) {
+            std::mem::replace(first.as_mut(), Split::Window(0))
+        } else {
+            return first.remove_window(window) || second.remove_window(window);
+        };
+        *self = remaining;
+        true
+    }
+    pub fn renumber_after_removal(&mut self, removed_window: usize) {
+        match self {
+            Split::Window(current) => {
+                if *current > removed_window {
+                    *current -= 1;
+                }
+            }
+            Split::Node { first, second, .. } => {
+                first.renumber_after_removal(removed_window);
+                second.renumber_after_removal(removed_window);
+            }
+        }
+    }
+    pub fn resize_window(&mut self, window: usize, change: i16) -> bool {
+        let Split::Node {
+            percentage,
+            first,
+            second,
+            ..
+        } = self
+        else {
+            return false;
+        };
+        let change = if matches!(first.as_ref(), Split::Window(current) if *current == window) {
+            change
+        } else if matches!(second.as_ref(), Split::Window(current) if *current == window) {
+            -change
+        } else {
+            return first.resize_window(window, change) || second.resize_window(window, change);
+        };
+        *percentage = (*percentage as i16 + change).clamp(10, 90) as u16;
+        true
+    }
+}
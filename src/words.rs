@@ -0,0 +1,83 @@
+use std::ops::Range;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    grapheme_reader::{GraphemeReader, line_end, line_start},
+    text_representation::TextRepresentation,
+};
+
+// a piece of a line between two unicode word boundaries, in graphemes of the rope
+struct Segment {
+    range: Range<usize>,
+    is_whitespace: bool,
+}
+
+// splits the logical line holding the index at its word boundaries, so that words never cross
+// a line and wrapped rows make no difference
+fn line_segments(
+    text_representation: &dyn TextRepresentation,
+    index: usize,
+) -> (Range<usize>, Vec<Segment>) {
+    let mut reader = GraphemeReader::new(text_representation);
+    let start = line_start(&mut reader, index);
+    let end = line_end(&mut reader, index);
+    let mut line = String::new();
+    if end > start {
+        text_representation.collect_substring(&mut line, (start, end - 1));
+    }
+    let mut segments = Vec::new();
+    let mut position = start;
+    for (_, word) in line.split_word_bound_indices() {
+        let length = word.graphemes(true).count();
+        segments.push(Segment {
+            range: position..position + length,
+            is_whitespace: word.chars().all(char::is_whitespace),
+        });
+        position += length;
+    }
+    (start..end, segments)
+}
+
+// the start of the next word, or the end of the line when no word is left on it
+pub fn next_word_boundary(text_representation: &dyn TextRepresentation, index: usize) -> usize {
+    let (line, segments) = line_segments(text_representation, index);
+    if index >= line.end {
+        return (line.end + 1).min(text_representation.length());
+    }
+    let mut segments = segments
+        .iter()
+        .skip_while(|segment| segment.range.end <= index);
+    let mut boundary = segments
+        .next()
+        .map_or(line.end, |segment| segment.range.end);
+    for segment in segments {
+        if !segment.is_whitespace {
+            break;
+        }
+        boundary = segment.range.end;
+    }
+    boundary
+}
+
+// the start of the word before the index, or the end of the previous line at the start of one
+pub fn previous_word_boundary(text_representation: &dyn TextRepresentation, index: usize) -> usize {
+    let (line, segments) = line_segments(text_representation, index);
+    if index <= line.start {
+        return index.saturating_sub(1);
+    }
+    segments
+        .iter()
+        .rev()
+        .find(|segment| segment.range.start < index && !segment.is_whitespace)
+        .map_or(line.start, |segment| segment.range.start)
+}
+
+// the word, run of whitespace or punctuation the index sits on
+pub fn word_at(text_representation: &dyn TextRepresentation, index: usize) -> Range<usize> {
+    let (line, segments) = line_segments(text_representation, index);
+    segments
+        .into_iter()
+        .find(|segment| segment.range.contains(&index))
+        .map_or(line.end..line.end, |segment| segment.range)
+}